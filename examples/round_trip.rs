@@ -0,0 +1,42 @@
+use bmf_parse::*;
+use std::io::Cursor;
+
+fn check(name: &str, input: &[u8]) -> Result<(), ParseError> {
+    let boxes = parse_mp4(input)?;
+    assert!(
+        write_mp4(&boxes).unwrap() == input,
+        "{}: parse_mp4 round trip",
+        name
+    );
+
+    let boxes = parse_mp4_ref(input)?;
+    let boxes: Vec<_> = boxes.into_iter().map(Mp4BoxRef::into_owned).collect();
+    assert!(
+        write_mp4(&boxes).unwrap() == input,
+        "{}: parse_mp4_ref round trip",
+        name
+    );
+
+    let mut reader = Cursor::new(input);
+    let mut boxes = parse_mp4_reader(&mut reader)?;
+    load_payloads(&mut boxes, &mut reader).unwrap();
+    assert!(
+        write_mp4(&boxes).unwrap() == input,
+        "{}: parse_mp4_reader round trip",
+        name
+    );
+
+    println!("{}: {} bytes round trip", name, input.len());
+    Ok(())
+}
+
+// Check that writing the files passed as arguments reproduces them,
+// the generated sample is covered by `tests/round_trip.rs`
+fn main() -> Result<(), ParseError> {
+    for path in std::env::args().skip(1) {
+        let input = std::fs::read(&path).unwrap();
+        check(&path, &input)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,187 @@
+use crate::base::*;
+use crate::boxes::*;
+use crate::reader::*;
+use crate::{check_loaded, child_lists_mut, ParseOutput, Skipped};
+use std::io::{self, SeekFrom};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt, AsyncWrite, AsyncWriteExt};
+
+/// Async `parse_mp4_reader`, payloads of `Mdat`, `Skip` kinds and unknown
+/// top-level boxes are left in the reader as `Payload::Range`
+pub async fn parse_mp4_async<R: AsyncRead + AsyncSeek + Unpin>(
+    reader: &mut R,
+) -> Result<Vec<Mp4Box>, ParseError> {
+    parse_mp4_async_with(reader, &ParseOptions::default())
+        .await
+        .map(|output| output.boxes)
+}
+
+// Async `reader::resync`
+async fn resync<R: AsyncRead + AsyncSeek + Unpin>(
+    reader: &mut R,
+    mut offset: u64,
+    end: u64,
+    state: &ParserState,
+) -> Result<u64, ParseError> {
+    while offset < end {
+        let mut window = resync_window(offset, end);
+        reader
+            .seek(SeekFrom::Start(offset))
+            .await
+            .map_err(|err| io_error(state, err))?;
+        reader
+            .read_exact(&mut window)
+            .await
+            .map_err(|err| io_error(state, err))?;
+
+        if let Some(next) = find_header(&window, offset, end) {
+            return Ok(next);
+        }
+        offset += RESYNC_WINDOW;
+    }
+
+    Ok(end)
+}
+
+/// Async `parse_mp4_reader_with`
+pub async fn parse_mp4_async_with<R: AsyncRead + AsyncSeek + Unpin>(
+    reader: &mut R,
+    options: &ParseOptions,
+) -> Result<ParseOutput, ParseError> {
+    let mut state = ParserState::with_options(options.clone());
+    let mut boxes = vec![];
+    let mut skipped = vec![];
+
+    let mut offset = reader
+        .stream_position()
+        .await
+        .map_err(|err| io_error(&state, err))?;
+    let end = reader
+        .seek(SeekFrom::End(0))
+        .await
+        .map_err(|err| io_error(&state, err))?;
+
+    while offset < end {
+        // Largest header is size + type + largesize
+        let mut header = [0; 16];
+        let header = &mut header[..(end - offset).min(16) as usize];
+        reader
+            .seek(SeekFrom::Start(offset))
+            .await
+            .map_err(|err| io_error(&state, err))?;
+        reader
+            .read_exact(header)
+            .await
+            .map_err(|err| io_error(&state, err))?;
+
+        let (type_, box_size, start, length) = match read_box_range(header, offset, end, &mut state)
+        {
+            Ok(range) => range,
+            Err(error) if options.recover => {
+                let next = resync(reader, offset + 1, end, &state).await?;
+                skipped.push(Skipped {
+                    range: offset..next,
+                    error,
+                });
+                offset = next;
+                continue;
+            }
+            Err(err) => return Err(err),
+        };
+
+        let data = Payload::Range {
+            offset: start,
+            len: length,
+        };
+        // Checked first so filtered mdat, free and skip boxes come back unknown too
+        let payload = match state.is_selected(type_) {
+            true => payload_box(type_, box_size, data),
+            false => Err(data),
+        };
+        let box_ = match payload {
+            Ok(box_) => Ok(box_),
+            // Filtered out, left in the reader like unknown boxes
+            Err(data) if !state.is_selected(type_) => Ok(Mp4Box::Unknown {
+                box_type: type_.to_ne_bytes(),
+                box_size,
+                data,
+            }),
+            // Metadata box, read it whole and parse it in memory
+            Err(_) => match payload_buffer(start, length, &mut state) {
+                Ok(mut data) => {
+                    reader
+                        .seek(SeekFrom::Start(start))
+                        .await
+                        .map_err(|err| io_error(&state, err))?;
+                    reader
+                        .read_exact(&mut data)
+                        .await
+                        .map_err(|err| io_error(&state, err))?;
+
+                    parse_payload(type_, box_size, &data, 0, &mut state)
+                }
+                Err(err) => Err(err),
+            },
+        };
+
+        match box_ {
+            Ok(box_) => boxes.push(box_),
+            Err(error) if options.recover => {
+                skipped.push(Skipped {
+                    range: offset..start + length,
+                    error,
+                });
+                state.unterminated = false;
+            }
+            Err(err) => return Err(err),
+        }
+        offset = start + length;
+    }
+
+    Ok(ParseOutput {
+        boxes,
+        warnings: state.warnings,
+        skipped,
+    })
+}
+
+/// Async `load_payloads`
+pub async fn load_payloads_async<R: AsyncRead + AsyncSeek + Unpin>(
+    boxes: &mut [Mp4Box],
+    reader: &mut R,
+) -> io::Result<()> {
+    let mut next_load = vec![boxes];
+
+    while let Some(boxes) = next_load.pop() {
+        for box_ in boxes {
+            if let Some(data) = box_.payload_mut() {
+                if let Payload::Range { offset, len } = *data {
+                    let mut buf = vec![0; len as usize];
+                    reader.seek(SeekFrom::Start(offset)).await?;
+                    reader.read_exact(&mut buf).await?;
+                    *data = Payload::Data(buf);
+                }
+            }
+
+            next_load.extend(child_lists_mut(box_).map(Vec::as_mut_slice));
+        }
+    }
+
+    Ok(())
+}
+
+/// Async `write_mp4`, writes one top-level box at a time
+pub async fn write_mp4_async<W: AsyncWrite + Unpin>(
+    boxes: &[Mp4Box],
+    writer: &mut W,
+) -> io::Result<()> {
+    check_loaded(boxes)?;
+    let mut buf = vec![];
+
+    for box_ in boxes {
+        buf.clear();
+        box_.write(&mut buf);
+        writer.write_all(&buf).await?;
+    }
+
+    writer.flush().await
+}
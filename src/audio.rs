@@ -0,0 +1,1066 @@
+use crate::base::*;
+use crate::bits::BitReader;
+use crate::r#macro::*;
+
+// Sampling rates of `sampling_frequency_index`, ISO/IEC 14496-3
+const SAMPLING_FREQUENCIES: [u32; 13] = [
+    96000, 88200, 64000, 48000, 44100, 32000, 24000, 22050, 16000, 12000, 11025, 8000, 7350,
+];
+
+/// Elementary stream descriptor box, ISO/IEC 14496-14
+#[derive(Debug)]
+pub struct BoxEsds {
+    pub header: Option<(u8, u32)>,
+    pub box_size: BoxSize,
+    pub descriptor: Descriptor,
+
+    pub layout: Layout,
+}
+
+/// MPEG-4 descriptor, ISO/IEC 14496-1
+#[derive(Debug)]
+pub struct Descriptor {
+    pub tag: u8,
+    /// Bytes taken by the size field, muxers often pad it to 4
+    pub size_length: u8,
+    pub data: DescriptorData,
+}
+
+#[derive(Debug)]
+pub enum DescriptorData {
+    Es(EsDescriptor),
+    DecoderConfig(DecoderConfigDescriptor),
+    DecoderSpecificInfo(Vec<u8>),
+    // Descriptor without a typed layout, e.g. SLConfigDescriptor
+    Raw(Vec<u8>),
+}
+
+#[derive(Debug)]
+pub struct EsDescriptor {
+    pub es_id: u16,
+    pub stream_priority: u8,           // 5 bits
+    pub depends_on_es_id: Option<u16>, // streamDependenceFlag
+    pub url: Option<Vec<u8>>,          // URL_Flag, length is 8 bits
+    pub ocr_es_id: Option<u16>,        // OCRstreamFlag
+    pub descriptors: Vec<Descriptor>,
+}
+
+#[derive(Debug)]
+pub struct DecoderConfigDescriptor {
+    pub object_type_indication: u8,
+    pub stream_type: u8, // 6 bits
+    pub up_stream: bool,
+    pub _reserved: u8,       // 1 bit
+    pub buffer_size_db: u32, // 24 bits
+    pub max_bitrate: u32,
+    pub avg_bitrate: u32,
+    pub descriptors: Vec<Descriptor>,
+}
+
+/// AudioSpecificConfig, ISO/IEC 14496-3, decoded from the DecoderSpecificInfo
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AudioSpecificConfig {
+    pub audio_object_type: u8,
+    pub sampling_frequency_index: u8,
+    pub sampling_frequency: Option<u32>, // Only if the index is 0xF
+    pub channel_configuration: u8,
+
+    // Spectral band replication and parametric stereo, signalled explicitly
+    // or as a backward compatible extension
+    pub sbr_present: bool,
+    pub ps_present: bool,
+    pub extension_sampling_frequency_index: Option<u8>,
+    pub extension_sampling_frequency: Option<u32>,
+}
+
+impl Descriptor {
+    pub const ES: u8 = 0x03;
+    pub const DECODER_CONFIG: u8 = 0x04;
+    pub const DECODER_SPECIFIC_INFO: u8 = 0x05;
+    pub const SL_CONFIG: u8 = 0x06;
+
+    // `depth` counts the descriptors this one is nested in, each is charged
+    // against `Limits::max_depth` like a box
+    fn parse(input: &[u8], state: &mut ParserState, depth: usize) -> Result<Self, ParseError> {
+        let depth = depth + 1;
+        if state.path.len() + depth > state.options.limits.max_depth {
+            return Err(state.error(ParseErrorKind::TooDeep(state.path.len() + depth)));
+        }
+
+        let tag = read_field!(input state; tag: u8);
+
+        // Size is 7 bits per byte, the top bit continues it
+        state.field = Some("size");
+        let mut size = 0usize;
+        let mut size_length = 0;
+        loop {
+            let byte = read(input, state, 1)?[0];
+            size = (size << 7) | (byte & 0x7F) as usize;
+            size_length += 1;
+            if byte & 0x80 == 0 || size_length == 4 {
+                break;
+            }
+        }
+
+        // Fields past the end of the descriptor read as truncated
+        let available = input.len() - state.offset;
+        if size > available {
+            return Err(state.error(ParseErrorKind::Truncated {
+                needed: size as u64,
+                available: available as u64,
+            }));
+        }
+        let input = &input[..state.offset + size];
+
+        let data = match tag {
+            Self::ES => DescriptorData::Es(EsDescriptor::parse(input, state, depth)?),
+            Self::DECODER_CONFIG => {
+                DescriptorData::DecoderConfig(DecoderConfigDescriptor::parse(input, state, depth)?)
+            }
+            Self::DECODER_SPECIFIC_INFO => {
+                state.field = Some("decoder_specific_info");
+                DescriptorData::DecoderSpecificInfo(read(input, state, size)?.to_vec())
+            }
+            _ => {
+                state.field = Some("data");
+                DescriptorData::Raw(read(input, state, size)?.to_vec())
+            }
+        };
+
+        Ok(Self {
+            tag,
+            size_length,
+            data,
+        })
+    }
+
+    fn write(&self, output: &mut Vec<u8>) {
+        let mut data = Vec::new();
+        match &self.data {
+            DescriptorData::Es(es) => es.write(&mut data),
+            DescriptorData::DecoderConfig(config) => config.write(&mut data),
+            DescriptorData::DecoderSpecificInfo(info) => data.extend_from_slice(info),
+            DescriptorData::Raw(raw) => data.extend_from_slice(raw),
+        }
+
+        // Keep the original padding unless the size no longer fits in it
+        let mut size_length = self.size_length.clamp(1, 4) as usize;
+        while size_length < 4 && data.len() >> (7 * size_length) != 0 {
+            size_length += 1;
+        }
+
+        output.push(self.tag);
+        for i in (0..size_length).rev() {
+            let more = if i > 0 { 0x80 } else { 0 };
+            output.push(more | (data.len() >> (7 * i)) as u8 & 0x7F);
+        }
+        output.extend(data);
+    }
+}
+
+// Child descriptors fill the rest of their parent
+fn parse_descriptors(
+    input: &[u8],
+    state: &mut ParserState,
+    depth: usize,
+) -> Result<Vec<Descriptor>, ParseError> {
+    let mut descriptors = vec![];
+    while !is_empty(input, state) {
+        descriptors.push(Descriptor::parse(input, state, depth)?);
+    }
+    Ok(descriptors)
+}
+
+impl EsDescriptor {
+    pub fn decoder_config(&self) -> Option<&DecoderConfigDescriptor> {
+        self.descriptors
+            .iter()
+            .find_map(|descriptor| match &descriptor.data {
+                DescriptorData::DecoderConfig(config) => Some(config),
+                _ => None,
+            })
+    }
+
+    fn parse(input: &[u8], state: &mut ParserState, depth: usize) -> Result<Self, ParseError> {
+        let es_id = read_field!(input state; es_id: u16);
+        let flags = read_field!(input state; stream_priority: u8);
+
+        let depends_on_es_id = if flags & 0x80 != 0 {
+            Some(read_field!(input state; depends_on_es_id: u16))
+        } else {
+            None
+        };
+        let url = if flags & 0x40 != 0 {
+            let length = read_field!(input state; url_length: u8);
+            state.field = Some("url");
+            Some(read(input, state, length as usize)?.to_vec())
+        } else {
+            None
+        };
+        let ocr_es_id = if flags & 0x20 != 0 {
+            Some(read_field!(input state; ocr_es_id: u16))
+        } else {
+            None
+        };
+
+        Ok(Self {
+            es_id,
+            stream_priority: flags & 0x1F,
+            depends_on_es_id,
+            url,
+            ocr_es_id,
+            descriptors: parse_descriptors(input, state, depth)?,
+        })
+    }
+
+    fn write(&self, output: &mut Vec<u8>) {
+        output.extend_from_slice(&u16::to_be_bytes(self.es_id));
+        output.push(
+            ((self.depends_on_es_id.is_some() as u8) << 7)
+                | ((self.url.is_some() as u8) << 6)
+                | ((self.ocr_es_id.is_some() as u8) << 5)
+                | (self.stream_priority & 0x1F),
+        );
+
+        if let Some(es_id) = self.depends_on_es_id {
+            output.extend_from_slice(&u16::to_be_bytes(es_id));
+        }
+        if let Some(url) = &self.url {
+            output.push(url.len() as u8);
+            output.extend_from_slice(url);
+        }
+        if let Some(es_id) = self.ocr_es_id {
+            output.extend_from_slice(&u16::to_be_bytes(es_id));
+        }
+
+        for descriptor in &self.descriptors {
+            descriptor.write(output);
+        }
+    }
+}
+
+impl DecoderConfigDescriptor {
+    pub fn decoder_specific_info(&self) -> Option<&[u8]> {
+        self.descriptors
+            .iter()
+            .find_map(|descriptor| match &descriptor.data {
+                DescriptorData::DecoderSpecificInfo(info) => Some(info.as_slice()),
+                _ => None,
+            })
+    }
+
+    fn parse(input: &[u8], state: &mut ParserState, depth: usize) -> Result<Self, ParseError> {
+        let object_type_indication = read_field!(input state; object_type_indication: u8);
+        let stream_type = read_field!(input state; stream_type: u8);
+        let buffer_size_db = read_field!(input state; buffer_size_db: [u8; 3]);
+        let max_bitrate = read_field!(input state; max_bitrate: u32);
+        let avg_bitrate = read_field!(input state; avg_bitrate: u32);
+
+        Ok(Self {
+            object_type_indication,
+            stream_type: stream_type >> 2,
+            up_stream: stream_type & 0x02 != 0,
+            _reserved: stream_type & 0x01,
+            buffer_size_db: u32::from_be_bytes([
+                0,
+                buffer_size_db[0],
+                buffer_size_db[1],
+                buffer_size_db[2],
+            ]),
+            max_bitrate,
+            avg_bitrate,
+            descriptors: parse_descriptors(input, state, depth)?,
+        })
+    }
+
+    fn write(&self, output: &mut Vec<u8>) {
+        output.push(self.object_type_indication);
+        output.push(
+            (self.stream_type << 2) | ((self.up_stream as u8) << 1) | (self._reserved & 0x01),
+        );
+        output.extend_from_slice(&u32::to_be_bytes(self.buffer_size_db)[1..]);
+        output.extend_from_slice(&u32::to_be_bytes(self.max_bitrate));
+        output.extend_from_slice(&u32::to_be_bytes(self.avg_bitrate));
+
+        for descriptor in &self.descriptors {
+            descriptor.write(output);
+        }
+    }
+}
+
+impl AudioSpecificConfig {
+    /// Decode the DecoderSpecificInfo of an MPEG-4 audio stream, `None` if it is cut short
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        let mut bits = BitReader::new(data);
+
+        let mut audio_object_type = read_audio_object_type(&mut bits)?;
+        let (sampling_frequency_index, sampling_frequency) = read_sampling_frequency(&mut bits)?;
+        let channel_configuration = bits.read(4)? as u8;
+
+        let mut config = Self {
+            audio_object_type,
+            sampling_frequency_index,
+            sampling_frequency,
+            channel_configuration,
+            sbr_present: false,
+            ps_present: false,
+            extension_sampling_frequency_index: None,
+            extension_sampling_frequency: None,
+        };
+
+        // Explicit SBR, the core object type follows
+        if audio_object_type == 5 || audio_object_type == 29 {
+            config.sbr_present = true;
+            config.ps_present = audio_object_type == 29;
+
+            let (index, frequency) = read_sampling_frequency(&mut bits)?;
+            config.extension_sampling_frequency_index = Some(index);
+            config.extension_sampling_frequency = frequency;
+
+            audio_object_type = read_audio_object_type(&mut bits)?;
+            config.audio_object_type = audio_object_type;
+            if audio_object_type == 22 {
+                bits.read(4)?; // extensionChannelConfiguration
+            }
+        } else if skip_ga_specific_config(&mut bits, audio_object_type, channel_configuration)
+            .is_some()
+        {
+            // Backward compatible SBR and PS signalling after the core config
+            if bits.remaining() >= 16 && bits.read(11)? == 0x2B7 {
+                let extension_type = read_audio_object_type(&mut bits)?;
+                if extension_type == 5 {
+                    config.sbr_present = bits.read_flag()?;
+                    if config.sbr_present {
+                        let (index, frequency) = read_sampling_frequency(&mut bits)?;
+                        config.extension_sampling_frequency_index = Some(index);
+                        config.extension_sampling_frequency = frequency;
+
+                        if bits.remaining() >= 12 && bits.read(11)? == 0x548 {
+                            config.ps_present = bits.read_flag()?;
+                        }
+                    }
+                }
+            }
+        }
+
+        Some(config)
+    }
+
+    /// Core sampling rate in Hz
+    pub fn sample_rate(&self) -> Option<u32> {
+        self.sampling_frequency.or_else(|| {
+            SAMPLING_FREQUENCIES
+                .get(self.sampling_frequency_index as usize)
+                .copied()
+        })
+    }
+
+    /// Sampling rate in Hz after SBR, the core rate without it
+    pub fn output_sample_rate(&self) -> Option<u32> {
+        match self.extension_sampling_frequency_index {
+            Some(index) if self.sbr_present => self
+                .extension_sampling_frequency
+                .or_else(|| SAMPLING_FREQUENCIES.get(index as usize).copied()),
+            _ => self.sample_rate(),
+        }
+    }
+}
+
+fn read_audio_object_type(bits: &mut BitReader) -> Option<u8> {
+    let audio_object_type = bits.read(5)? as u8;
+    if audio_object_type == 31 {
+        Some(32 + bits.read(6)? as u8)
+    } else {
+        Some(audio_object_type)
+    }
+}
+
+fn read_sampling_frequency(bits: &mut BitReader) -> Option<(u8, Option<u32>)> {
+    let index = bits.read(4)? as u8;
+    if index == 0xF {
+        Some((index, Some(bits.read(24)?)))
+    } else {
+        Some((index, None))
+    }
+}
+
+// Skip GASpecificConfig so the extension signalling after it can be read.
+// `None` if the object type isn't covered or the config has a program_config_element
+fn skip_ga_specific_config(
+    bits: &mut BitReader,
+    audio_object_type: u8,
+    channel_configuration: u8,
+) -> Option<()> {
+    if !matches!(audio_object_type, 1..=4 | 6 | 7 | 17 | 19..=23) || channel_configuration == 0 {
+        return None;
+    }
+
+    bits.read(1)?; // frameLengthFlag
+    if bits.read_flag()? {
+        bits.read(14)?; // coreCoderDelay
+    }
+    let extension_flag = bits.read_flag()?;
+    if audio_object_type == 6 || audio_object_type == 20 {
+        bits.read(3)?; // layerNr
+    }
+    if extension_flag {
+        if audio_object_type == 22 {
+            bits.read(16)?; // numOfSubFrame, layer_length
+        }
+        if matches!(audio_object_type, 17 | 19 | 20 | 23) {
+            bits.read(3)?; // Resilience flags
+        }
+        bits.read(1)?; // extensionFlag3
+    }
+    if matches!(audio_object_type, 17 | 19..=23) {
+        bits.read(2)?; // epConfig
+    }
+
+    Some(())
+}
+
+impl BoxEsds {
+    pub fn es_descriptor(&self) -> Option<&EsDescriptor> {
+        match &self.descriptor.data {
+            DescriptorData::Es(es) => Some(es),
+            _ => None,
+        }
+    }
+
+    pub fn decoder_config(&self) -> Option<&DecoderConfigDescriptor> {
+        self.es_descriptor()?.decoder_config()
+    }
+
+    /// Decoded AudioSpecificConfig of an MPEG-4 audio stream
+    pub fn audio_specific_config(&self) -> Option<AudioSpecificConfig> {
+        let config = self.decoder_config()?;
+        if config.object_type_indication != 0x40 {
+            return None;
+        }
+        AudioSpecificConfig::parse(config.decoder_specific_info()?)
+    }
+
+    fn parse_fields(
+        input: &[u8],
+        state: &mut ParserState,
+        _header: &Option<(u8, u32)>,
+    ) -> Result<Self, ParseError> {
+        Ok(Self {
+            header: None,
+            box_size: BoxSize::Compact,
+            descriptor: Descriptor::parse(input, state, 0)?,
+            layout: Layout::default(),
+        })
+    }
+
+    fn write_fields(&self, output: &mut Vec<u8>) {
+        self.descriptor.write(output);
+    }
+}
+
+custom_box!(BoxEsds = b"esds", Full);
+
+/// Opus specific box, Encapsulation of Opus in ISO Base Media File Format
+#[derive(Debug)]
+pub struct BoxDops {
+    pub box_size: BoxSize,
+    pub version: u8,
+    pub output_channel_count: u8,
+    pub pre_skip: u16,
+    pub input_sample_rate: u32,
+    pub output_gain: i16, // Q7.8 dB
+    pub channel_mapping_family: u8,
+    pub channel_mapping_table: Option<ChannelMappingTable>, // Only if the family isn't 0
+
+    pub layout: Layout,
+}
+
+#[derive(Debug)]
+pub struct ChannelMappingTable {
+    pub stream_count: u8,
+    pub coupled_count: u8,
+    pub channel_mapping: Vec<u8>, // One per output channel
+}
+
+impl BoxDops {
+    fn parse_fields(
+        input: &[u8],
+        state: &mut ParserState,
+        _header: &Option<(u8, u32)>,
+    ) -> Result<Self, ParseError> {
+        let version = read_field!(input state; version: u8);
+        let output_channel_count = read_field!(input state; output_channel_count: u8);
+        let pre_skip = read_field!(input state; pre_skip: u16);
+        let input_sample_rate = read_field!(input state; input_sample_rate: u32);
+        let output_gain = read_field!(input state; output_gain: i16);
+        let channel_mapping_family = read_field!(input state; channel_mapping_family: u8);
+
+        let channel_mapping_table = if channel_mapping_family != 0 {
+            let stream_count = read_field!(input state; stream_count: u8);
+            let coupled_count = read_field!(input state; coupled_count: u8);
+            state.field = Some("channel_mapping");
+            let channel_mapping = read(input, state, output_channel_count as usize)?.to_vec();
+
+            Some(ChannelMappingTable {
+                stream_count,
+                coupled_count,
+                channel_mapping,
+            })
+        } else {
+            None
+        };
+
+        Ok(Self {
+            box_size: BoxSize::Compact,
+            version,
+            output_channel_count,
+            pre_skip,
+            input_sample_rate,
+            output_gain,
+            channel_mapping_family,
+            channel_mapping_table,
+            layout: Layout::default(),
+        })
+    }
+
+    fn write_fields(&self, output: &mut Vec<u8>) {
+        mp4box_gen! { @write output self.version; u8 }
+        mp4box_gen! { @write output self.output_channel_count; u8 }
+        mp4box_gen! { @write output self.pre_skip; u16 }
+        mp4box_gen! { @write output self.input_sample_rate; u32 }
+        mp4box_gen! { @write output self.output_gain; i16 }
+        mp4box_gen! { @write output self.channel_mapping_family; u8 }
+        if let Some(table) = &self.channel_mapping_table {
+            output.push(table.stream_count);
+            output.push(table.coupled_count);
+            output.extend_from_slice(&table.channel_mapping);
+        }
+    }
+}
+
+custom_box!(BoxDops = b"dOps");
+
+/// FLAC specific box, Encapsulation of FLAC in ISO Base Media File Format
+#[derive(Debug)]
+pub struct BoxDfla {
+    pub header: Option<(u8, u32)>,
+    pub box_size: BoxSize,
+    pub blocks: Vec<FlacMetadataBlock>,
+
+    pub layout: Layout,
+}
+
+#[derive(Debug)]
+pub struct FlacMetadataBlock {
+    pub last_metadata_block_flag: bool,
+    pub block_type: u8, // 7 bits
+    pub data: FlacBlockData,
+}
+
+#[derive(Debug)]
+pub enum FlacBlockData {
+    StreamInfo(FlacStreamInfo),
+    // Any other block, or a STREAMINFO of the wrong length
+    Raw(Vec<u8>), // Length is 24 bits
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlacStreamInfo {
+    pub min_block_size: u16,
+    pub max_block_size: u16,
+    pub min_frame_size: u32,           // 24 bits
+    pub max_frame_size: u32,           // 24 bits
+    pub sample_rate: u32,              // 20 bits
+    pub channels_minus_one: u8,        // 3 bits
+    pub bits_per_sample_minus_one: u8, // 5 bits
+    pub total_samples: u64,            // 36 bits
+    pub md5: [u8; 16],
+}
+
+impl FlacMetadataBlock {
+    pub const STREAMINFO: u8 = 0;
+}
+
+impl BoxDfla {
+    pub fn stream_info(&self) -> Option<&FlacStreamInfo> {
+        self.blocks.iter().find_map(|block| match &block.data {
+            FlacBlockData::StreamInfo(info) => Some(info),
+            _ => None,
+        })
+    }
+
+    fn parse_fields(
+        input: &[u8],
+        state: &mut ParserState,
+        _header: &Option<(u8, u32)>,
+    ) -> Result<Self, ParseError> {
+        let mut blocks = vec![];
+
+        // Blocks run up to the one flagged last
+        while input.len() - state.offset >= 4 {
+            let block_header = read_field!(input state; block_header: u32);
+            let length = (block_header & 0xFF_FFFF) as usize;
+            let block_type = (block_header >> 24) as u8 & 0x7F;
+            let last_metadata_block_flag = block_header & 0x8000_0000 != 0;
+
+            state.field = Some("block_data");
+            let data = read(input, state, length)?;
+            let data = if block_type == FlacMetadataBlock::STREAMINFO && length == 34 {
+                let sizes = u64::from_be_bytes(data[4..12].try_into().unwrap());
+                let format = u64::from_be_bytes(data[10..18].try_into().unwrap());
+                FlacBlockData::StreamInfo(FlacStreamInfo {
+                    min_block_size: u16::from_be_bytes([data[0], data[1]]),
+                    max_block_size: u16::from_be_bytes([data[2], data[3]]),
+                    min_frame_size: (sizes >> 40) as u32,
+                    max_frame_size: (sizes >> 16) as u32 & 0xFF_FFFF,
+                    sample_rate: (format >> 44) as u32,
+                    channels_minus_one: (format >> 41) as u8 & 0x07,
+                    bits_per_sample_minus_one: (format >> 36) as u8 & 0x1F,
+                    total_samples: format & 0xF_FFFF_FFFF,
+                    md5: data[18..].try_into().unwrap(),
+                })
+            } else {
+                FlacBlockData::Raw(data.to_vec())
+            };
+
+            blocks.push(FlacMetadataBlock {
+                last_metadata_block_flag,
+                block_type,
+                data,
+            });
+            if last_metadata_block_flag {
+                break;
+            }
+        }
+
+        Ok(Self {
+            header: None,
+            box_size: BoxSize::Compact,
+            blocks,
+            layout: Layout::default(),
+        })
+    }
+
+    fn write_fields(&self, output: &mut Vec<u8>) {
+        for block in &self.blocks {
+            let mut data = Vec::new();
+            match &block.data {
+                FlacBlockData::StreamInfo(info) => {
+                    data.extend_from_slice(&u16::to_be_bytes(info.min_block_size));
+                    data.extend_from_slice(&u16::to_be_bytes(info.max_block_size));
+                    data.extend_from_slice(&u32::to_be_bytes(info.min_frame_size)[1..]);
+                    data.extend_from_slice(&u32::to_be_bytes(info.max_frame_size)[1..]);
+                    let format = ((info.sample_rate as u64 & 0xF_FFFF) << 44)
+                        | ((info.channels_minus_one as u64 & 0x07) << 41)
+                        | ((info.bits_per_sample_minus_one as u64 & 0x1F) << 36)
+                        | (info.total_samples & 0xF_FFFF_FFFF);
+                    data.extend_from_slice(&u64::to_be_bytes(format));
+                    data.extend_from_slice(&info.md5);
+                }
+                FlacBlockData::Raw(raw) => data.extend_from_slice(raw),
+            }
+
+            let block_header = ((block.last_metadata_block_flag as u32) << 31)
+                | ((block.block_type as u32 & 0x7F) << 24)
+                | (data.len() as u32 & 0xFF_FFFF);
+            output.extend_from_slice(&u32::to_be_bytes(block_header));
+            output.extend(data);
+        }
+    }
+}
+
+custom_box!(BoxDfla = b"dfLa", Full);
+
+/// AC-3 specific box, ETSI TS 102 366 Annex F
+#[derive(Debug)]
+pub struct BoxDac3 {
+    pub box_size: BoxSize,
+    pub fscod: u8, // 2 bits
+    pub bsid: u8,  // 5 bits
+    pub bsmod: u8, // 3 bits
+    pub acmod: u8, // 3 bits
+    pub lfeon: bool,
+    pub bit_rate_code: u8, // 5 bits
+    pub _reserved: u8,     // 5 bits
+
+    pub layout: Layout,
+}
+
+impl BoxDac3 {
+    fn parse_fields(
+        input: &[u8],
+        state: &mut ParserState,
+        _header: &Option<(u8, u32)>,
+    ) -> Result<Self, ParseError> {
+        let fields = read_field!(input state; fscod: [u8; 3]);
+        let fields = u32::from_be_bytes([0, fields[0], fields[1], fields[2]]);
+
+        Ok(Self {
+            box_size: BoxSize::Compact,
+            fscod: (fields >> 22) as u8,
+            bsid: (fields >> 17) as u8 & 0x1F,
+            bsmod: (fields >> 14) as u8 & 0x07,
+            acmod: (fields >> 11) as u8 & 0x07,
+            lfeon: fields & 0x400 != 0,
+            bit_rate_code: (fields >> 5) as u8 & 0x1F,
+            _reserved: fields as u8 & 0x1F,
+            layout: Layout::default(),
+        })
+    }
+
+    fn write_fields(&self, output: &mut Vec<u8>) {
+        let fields = ((self.fscod as u32 & 0x03) << 22)
+            | ((self.bsid as u32 & 0x1F) << 17)
+            | ((self.bsmod as u32 & 0x07) << 14)
+            | ((self.acmod as u32 & 0x07) << 11)
+            | ((self.lfeon as u32) << 10)
+            | ((self.bit_rate_code as u32 & 0x1F) << 5)
+            | (self._reserved as u32 & 0x1F);
+        output.extend_from_slice(&u32::to_be_bytes(fields)[1..]);
+    }
+}
+
+custom_box!(BoxDac3 = b"dac3");
+
+/// Enhanced AC-3 specific box, ETSI TS 102 366 Annex F
+#[derive(Debug)]
+pub struct BoxDec3 {
+    pub box_size: BoxSize,
+    pub data_rate: u16,                // 13 bits, kbit/s
+    pub substreams: Vec<Ec3Substream>, // Count is 3 bits, minus one
+
+    // Dolby Atmos extension, flag_ec3_extension_type_a and complexity_index_type_a
+    pub extension: Vec<u8>,
+
+    pub layout: Layout,
+}
+
+/// Independent substream of an E-AC-3 stream
+#[derive(Debug)]
+pub struct Ec3Substream {
+    pub fscod: u8,     // 2 bits
+    pub bsid: u8,      // 5 bits
+    pub _reserved: u8, // 1 bit
+    pub asvc: bool,
+    pub bsmod: u8, // 3 bits
+    pub acmod: u8, // 3 bits
+    pub lfeon: bool,
+    pub _reserved1: u8,  // 3 bits
+    pub num_dep_sub: u8, // 4 bits
+
+    // 9 bits if there are dependent substreams, otherwise a reserved bit
+    pub chan_loc: u16,
+}
+
+impl BoxDec3 {
+    fn parse_fields(
+        input: &[u8],
+        state: &mut ParserState,
+        _header: &Option<(u8, u32)>,
+    ) -> Result<Self, ParseError> {
+        let data_rate = read_field!(input state; data_rate: u16);
+        let count = (data_rate & 0x07) as u64 + 1;
+
+        state.field = Some("substreams");
+        let substreams = read_entries(input, state, count, |input, state| {
+            let fields = read_field!(input state; fscod: [u8; 3]);
+            let num_dep_sub = (fields[2] >> 1) & 0x0F;
+            let chan_loc = if num_dep_sub > 0 {
+                let low = read_field!(input state; chan_loc: u8);
+                ((fields[2] as u16 & 0x01) << 8) | low as u16
+            } else {
+                fields[2] as u16 & 0x01
+            };
+
+            Ok(Ec3Substream {
+                fscod: fields[0] >> 6,
+                bsid: (fields[0] >> 1) & 0x1F,
+                _reserved: fields[0] & 0x01,
+                asvc: fields[1] & 0x80 != 0,
+                bsmod: (fields[1] >> 4) & 0x07,
+                acmod: (fields[1] >> 1) & 0x07,
+                lfeon: fields[1] & 0x01 != 0,
+                _reserved1: fields[2] >> 5,
+                num_dep_sub,
+                chan_loc,
+            })
+        })?;
+
+        state.field = Some("extension");
+        let extension = read(input, state, input.len() - state.offset)?.to_vec();
+
+        Ok(Self {
+            box_size: BoxSize::Compact,
+            data_rate: data_rate >> 3,
+            substreams,
+            extension,
+            layout: Layout::default(),
+        })
+    }
+
+    fn write_fields(&self, output: &mut Vec<u8>) {
+        let count = self.substreams.len().saturating_sub(1) as u16 & 0x07;
+        output.extend_from_slice(&u16::to_be_bytes((self.data_rate << 3) | count));
+
+        for substream in &self.substreams {
+            output.push(
+                ((substream.fscod & 0x03) << 6)
+                    | ((substream.bsid & 0x1F) << 1)
+                    | (substream._reserved & 0x01),
+            );
+            output.push(
+                ((substream.asvc as u8) << 7)
+                    | ((substream.bsmod & 0x07) << 4)
+                    | ((substream.acmod & 0x07) << 1)
+                    | substream.lfeon as u8,
+            );
+            output.push(
+                ((substream._reserved1 & 0x07) << 5)
+                    | ((substream.num_dep_sub & 0x0F) << 1)
+                    | (substream.chan_loc >> 8) as u8 & 0x01,
+            );
+            if substream.num_dep_sub > 0 {
+                output.push(substream.chan_loc as u8);
+            }
+        }
+        output.extend_from_slice(&self.extension);
+    }
+}
+
+custom_box!(BoxDec3 = b"dec3");
+
+/// AC-4 specific box, ETSI TS 103 190-2 Annex E
+#[derive(Debug)]
+pub struct BoxDac4 {
+    pub box_size: BoxSize,
+    pub ac4_dsi_version: u8,   // 3 bits
+    pub bitstream_version: u8, // 7 bits
+    pub fs_index: u8,          // 1 bit
+    pub frame_rate_index: u8,  // 4 bits
+    pub n_presentations: u16,  // 9 bits
+
+    // Bit-packed program ID, bit rate and presentations of the DSI
+    pub data: Vec<u8>,
+
+    pub layout: Layout,
+}
+
+impl BoxDac4 {
+    /// Sampling rate in Hz of the base sampling frequency
+    pub fn sample_rate(&self) -> u32 {
+        if self.fs_index == 0 {
+            44100
+        } else {
+            48000
+        }
+    }
+
+    fn parse_fields(
+        input: &[u8],
+        state: &mut ParserState,
+        _header: &Option<(u8, u32)>,
+    ) -> Result<Self, ParseError> {
+        let fields = read_field!(input state; ac4_dsi_version: [u8; 3]);
+        let fields = u32::from_be_bytes([0, fields[0], fields[1], fields[2]]);
+
+        state.field = Some("data");
+        let data = read(input, state, input.len() - state.offset)?.to_vec();
+
+        Ok(Self {
+            box_size: BoxSize::Compact,
+            ac4_dsi_version: (fields >> 21) as u8,
+            bitstream_version: (fields >> 14) as u8 & 0x7F,
+            fs_index: (fields >> 13) as u8 & 0x01,
+            frame_rate_index: (fields >> 9) as u8 & 0x0F,
+            n_presentations: fields as u16 & 0x1FF,
+            data,
+            layout: Layout::default(),
+        })
+    }
+
+    fn write_fields(&self, output: &mut Vec<u8>) {
+        let fields = ((self.ac4_dsi_version as u32 & 0x07) << 21)
+            | ((self.bitstream_version as u32 & 0x7F) << 14)
+            | ((self.fs_index as u32 & 0x01) << 13)
+            | ((self.frame_rate_index as u32 & 0x0F) << 9)
+            | (self.n_presentations as u32 & 0x1FF);
+        output.extend_from_slice(&u32::to_be_bytes(fields)[1..]);
+        output.extend_from_slice(&self.data);
+    }
+}
+
+custom_box!(BoxDac4 = b"dac4");
+
+/// Channel layout box, ISO/IEC 14496-12
+#[derive(Debug)]
+pub struct BoxChnl {
+    pub header: Option<(u8, u32)>,
+    pub box_size: BoxSize,
+    pub stream_structure: u8,   // 4 bits in version 1
+    pub format_ordering: u8,    // Version 1, 4 bits
+    pub base_channel_count: u8, // Version 1
+
+    // Only if the stream is channel structured
+    pub defined_layout: Option<u8>,
+    pub speaker_positions: Vec<SpeakerPosition>, // If the layout is 0, count is 8 bits in version 1
+
+    // Version 1, if the layout isn't 0
+    pub _reserved: u8,                // 4 bits
+    pub channel_order_definition: u8, // 3 bits
+
+    pub omitted_channels_map: Option<u64>,
+    pub object_count: Option<u8>, // Version 0, if the stream is object structured
+
+    pub layout: Layout,
+}
+
+#[derive(Debug)]
+pub struct SpeakerPosition {
+    pub speaker_position: u8,
+
+    // Only for explicit positions
+    pub azimuth: i16,
+    pub elevation: i8,
+}
+
+impl SpeakerPosition {
+    pub const EXPLICIT: u8 = 126;
+}
+
+impl BoxChnl {
+    pub const CHANNEL_STRUCTURED: u8 = 1;
+    pub const OBJECT_STRUCTURED: u8 = 2;
+
+    fn parse_fields(
+        input: &[u8],
+        state: &mut ParserState,
+        header: &Option<(u8, u32)>,
+    ) -> Result<Self, ParseError> {
+        let version = header.map_or(0, |(version, _)| version);
+
+        let mut stream_structure = read_field!(input state; stream_structure: u8);
+        let mut format_ordering = 0;
+        let mut base_channel_count = 0;
+        if version > 0 {
+            format_ordering = stream_structure & 0x0F;
+            stream_structure >>= 4;
+            base_channel_count = read_field!(input state; base_channel_count: u8);
+        }
+
+        let mut chnl = Self {
+            header: None,
+            box_size: BoxSize::Compact,
+            stream_structure,
+            format_ordering,
+            base_channel_count,
+            defined_layout: None,
+            speaker_positions: vec![],
+            _reserved: 0,
+            channel_order_definition: 0,
+            omitted_channels_map: None,
+            object_count: None,
+            layout: Layout::default(),
+        };
+
+        if stream_structure & Self::CHANNEL_STRUCTURED != 0 {
+            let defined_layout = read_field!(input state; defined_layout: u8);
+            chnl.defined_layout = Some(defined_layout);
+
+            if defined_layout == 0 {
+                state.field = Some("speaker_positions");
+                if version > 0 {
+                    let count = read_field!(input state; layout_channel_count: u8);
+                    chnl.speaker_positions =
+                        read_entries(input, state, count as u64, read_speaker_position)?;
+                } else {
+                    // Count comes from the sample entry, positions fill the box up to object_count
+                    let objects = (stream_structure & Self::OBJECT_STRUCTURED != 0) as usize;
+                    while input.len() - state.offset > objects {
+                        chnl.speaker_positions
+                            .push(read_speaker_position(input, state)?);
+                    }
+                }
+            } else if version > 0 {
+                let flags = read_field!(input state; channel_order_definition: u8);
+                chnl._reserved = flags >> 4;
+                chnl.channel_order_definition = (flags >> 1) & 0x07;
+                if flags & 0x01 != 0 {
+                    chnl.omitted_channels_map =
+                        Some(read_field!(input state; omitted_channels_map: u64));
+                }
+            } else {
+                chnl.omitted_channels_map =
+                    Some(read_field!(input state; omitted_channels_map: u64));
+            }
+        }
+
+        if version == 0 && stream_structure & Self::OBJECT_STRUCTURED != 0 {
+            chnl.object_count = Some(read_field!(input state; object_count: u8));
+        }
+
+        Ok(chnl)
+    }
+
+    fn write_fields(&self, output: &mut Vec<u8>) {
+        let version = self.header.map_or(0, |(version, _)| version);
+
+        if version > 0 {
+            output.push((self.stream_structure << 4) | (self.format_ordering & 0x0F));
+            output.push(self.base_channel_count);
+        } else {
+            output.push(self.stream_structure);
+        }
+
+        if let Some(defined_layout) = self.defined_layout {
+            output.push(defined_layout);
+            if defined_layout == 0 {
+                if version > 0 {
+                    output.push(self.speaker_positions.len() as u8);
+                }
+                for position in &self.speaker_positions {
+                    output.push(position.speaker_position);
+                    if position.speaker_position == SpeakerPosition::EXPLICIT {
+                        output.extend_from_slice(&i16::to_be_bytes(position.azimuth));
+                        output.extend_from_slice(&i8::to_be_bytes(position.elevation));
+                    }
+                }
+            } else if version > 0 {
+                output.push(
+                    (self._reserved << 4)
+                        | ((self.channel_order_definition & 0x07) << 1)
+                        | self.omitted_channels_map.is_some() as u8,
+                );
+            }
+        }
+
+        if let Some(map) = self.omitted_channels_map {
+            output.extend_from_slice(&u64::to_be_bytes(map));
+        }
+        if let Some(count) = self.object_count {
+            output.push(count);
+        }
+    }
+}
+
+fn read_speaker_position(
+    input: &[u8],
+    state: &mut ParserState,
+) -> Result<SpeakerPosition, ParseError> {
+    let speaker_position = read_field!(input state; speaker_position: u8);
+    let (azimuth, elevation) = if speaker_position == SpeakerPosition::EXPLICIT {
+        (
+            read_field!(input state; azimuth: i16),
+            read_field!(input state; elevation: i8),
+        )
+    } else {
+        (0, 0)
+    };
+
+    Ok(SpeakerPosition {
+        speaker_position,
+        azimuth,
+        elevation,
+    })
+}
+
+custom_box!(BoxChnl = b"chnl", Full);
@@ -1,14 +1,240 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::ops::Range;
+use std::sync::Arc;
+
+/// Per-call parser settings, see `parse_mp4_with`
+#[derive(Debug, Clone, Default)]
+pub struct ParseOptions {
+    /// Record `mdat`, and boxes `filter` rejects, as a `Payload::Range` into the input
+    /// instead of copying them
+    pub lazy_mdat: bool,
+    pub limits: Limits,
+    pub strictness: Strictness,
+    /// Skip past a top-level box that fails to parse and resume at the next plausible
+    /// box header, instead of returning the error
+    pub recover: bool,
+    /// Boxes to decode, the rest are kept as `Mp4Box::Unknown`. `None` decodes everything
+    pub filter: Option<BoxFilter>,
+}
+
+/// Chooses which boxes get decoded from their path, outermost type first.
+/// A container is only descended into if the filter accepts it
+#[derive(Clone)]
+pub struct BoxFilter(Arc<FilterFn>);
+
+type FilterFn = dyn Fn(&[[u8; 4]]) -> bool + Send + Sync;
+
+impl BoxFilter {
+    pub fn new(filter: impl Fn(&[[u8; 4]]) -> bool + Send + Sync + 'static) -> Self {
+        Self(Arc::new(filter))
+    }
+
+    /// Decode the boxes at `paths` such as `moof/traf/tfdt`, everything inside them,
+    /// and the containers leading to them
+    ///
+    /// # Panics
+    /// If a path has a type that isn't 4 bytes long
+    pub fn paths(paths: &[&str]) -> Self {
+        let paths: Vec<Vec<[u8; 4]>> = paths
+            .iter()
+            .map(|path| {
+                path.split('/')
+                    .map(|type_| {
+                        type_
+                            .as_bytes()
+                            .try_into()
+                            .unwrap_or_else(|_| panic!("box type {:?} is not 4 bytes", type_))
+                    })
+                    .collect()
+            })
+            .collect();
+
+        Self::new(move |box_path| {
+            paths.iter().any(|path| {
+                let len = path.len().min(box_path.len());
+                path[..len] == box_path[..len]
+            })
+        })
+    }
+
+    pub fn accepts(&self, path: &[[u8; 4]]) -> bool {
+        (self.0)(path)
+    }
+}
+
+impl fmt::Debug for BoxFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "BoxFilter")
+    }
+}
+
+/// What the parser does with input that breaks the spec but can still be read
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Strictness {
+    /// Fail with `ParseErrorKind::Nonconformant`
+    Strict,
+    /// Keep going and report a `ParseWarning`
+    #[default]
+    Lenient,
+}
+
+/// Bounds on what untrusted input can make the parser do
+#[derive(Debug, Clone)]
+pub struct Limits {
+    /// Deepest box nesting, top-level boxes are depth 1. Descriptors in `esds` count as boxes
+    pub max_depth: usize,
+    /// Entries a table may declare per byte left in its box.
+    /// Tables whose entries take no bytes are only bound by `max_alloc`
+    pub max_entries_per_byte: u64,
+    /// Total bytes the parser may allocate for entry tables,
+    /// plus the box being buffered when parsing from a reader
+    pub max_alloc: u64,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_depth: 64,
+            max_entries_per_byte: 1,
+            max_alloc: 1 << 30,
+        }
+    }
+}
+
 pub(crate) struct ParserState {
     pub(crate) offset: usize,
+    /// Absolute offset of the current input slice within the source
+    pub(crate) base: u64,
+    /// Types of the boxes currently being parsed, outermost first
+    pub(crate) path: Vec<u32>,
+    /// Field currently being read, for error reporting
+    pub(crate) field: Option<&'static str>,
+    pub(crate) options: ParseOptions,
+    /// Bytes allocated for entry tables so far
+    pub(crate) allocated: u64,
+    /// The last string read ran to the end of its input without a NUL
+    pub(crate) unterminated: bool,
+    pub(crate) warnings: Vec<ParseWarning>,
 }
 
-pub(crate) fn read<'a>(input: &'a [u8], state: &mut ParserState, n: usize) -> Option<&'a [u8]> {
-    if state.offset + n <= input.len() {
+impl ParserState {
+    pub(crate) fn new() -> Self {
+        Self::with_options(ParseOptions::default())
+    }
+
+    pub(crate) fn with_options(options: ParseOptions) -> Self {
+        Self {
+            offset: 0,
+            base: 0,
+            path: vec![],
+            field: None,
+            options,
+            allocated: 0,
+            unterminated: false,
+            warnings: vec![],
+        }
+    }
+
+    // Whether a raw payload of `type_` should be left in the input
+    pub(crate) fn is_lazy(&self, type_: u32) -> bool {
+        self.options.lazy_mdat && type_ == u32::from_ne_bytes(*b"mdat")
+    }
+
+    // Whether a `type_` box inside the current one should be decoded
+    pub(crate) fn is_selected(&self, type_: u32) -> bool {
+        match &self.options.filter {
+            Some(filter) => {
+                let path: Vec<[u8; 4]> = self
+                    .path
+                    .iter()
+                    .chain([&type_])
+                    .map(|type_| type_.to_ne_bytes())
+                    .collect();
+                filter.accepts(&path)
+            }
+            None => true,
+        }
+    }
+
+    // Descend into a box whose payload starts at `start` in the current input
+    pub(crate) fn enter(
+        &mut self,
+        type_: u32,
+        start: usize,
+    ) -> Result<(usize, u64, Option<&'static str>), ParseError> {
+        if self.path.len() >= self.options.limits.max_depth {
+            return Err(self.error_at(start, ParseErrorKind::TooDeep(self.path.len() + 1)));
+        }
+
+        let saved = (self.offset, self.base, self.field);
+        self.path.push(type_);
+        self.base += start as u64;
+        self.offset = 0;
+        self.field = None;
+        Ok(saved)
+    }
+
+    pub(crate) fn leave(&mut self, saved: (usize, u64, Option<&'static str>)) {
+        self.path.pop();
+        (self.offset, self.base, self.field) = saved;
+    }
+
+    pub(crate) fn error(&self, kind: ParseErrorKind) -> ParseError {
+        self.error_at(self.offset, kind)
+    }
+
+    pub(crate) fn error_at(&self, offset: usize, kind: ParseErrorKind) -> ParseError {
+        ParseError {
+            offset: self.base + offset as u64,
+            path: format_path(&self.path),
+            field: self.field,
+            kind,
+        }
+    }
+
+    // Report a spec violation, only an error in strict mode
+    pub(crate) fn warn_at(
+        &mut self,
+        offset: usize,
+        kind: ParseWarningKind,
+    ) -> Result<(), ParseError> {
+        if self.options.strictness == Strictness::Strict {
+            return Err(self.error_at(offset, ParseErrorKind::Nonconformant(kind)));
+        }
+
+        self.warnings.push(ParseWarning {
+            offset: self.base + offset as u64,
+            path: format_path(&self.path),
+            kind,
+        });
+        Ok(())
+    }
+}
+
+pub(crate) fn format_path(path: &[u32]) -> String {
+    path.iter()
+        .map(|type_| String::from_utf8_lossy(&type_.to_ne_bytes()).into_owned())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+pub(crate) fn read<'a>(
+    input: &'a [u8],
+    state: &mut ParserState,
+    n: usize,
+) -> Result<&'a [u8], ParseError> {
+    let available = input.len() - state.offset;
+    if n <= available {
         let slice = &input[state.offset..state.offset + n];
         state.offset += n;
-        Some(slice)
+        Ok(slice)
     } else {
-        None
+        Err(state.error(ParseErrorKind::Truncated {
+            needed: n as u64,
+            available: available as u64,
+        }))
     }
 }
 
@@ -22,3 +248,273 @@ pub enum Either<A, B> {
     A(A),
     B(B),
 }
+
+/// How a box header encoded its size
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BoxSize {
+    /// 32-bit size field
+    #[default]
+    Compact,
+    /// `size == 1`, with a 64-bit largesize after the type.
+    /// Also used when writing any box too large for a 32-bit size
+    Large,
+    /// `size == 0`, the box extends to the end of the file
+    ToEnd,
+}
+
+/// Raw payload of an opaque box
+pub enum Payload {
+    Data(Vec<u8>),
+    /// Left in the source at an absolute offset, read on demand
+    Range {
+        offset: u64,
+        len: u64,
+    },
+}
+
+impl Payload {
+    pub fn len(&self) -> u64 {
+        match self {
+            Payload::Data(data) => data.len() as u64,
+            Payload::Range { len, .. } => *len,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn is_loaded(&self) -> bool {
+        matches!(self, Payload::Data(_))
+    }
+
+    /// Read the payload from `reader` if it is still in the source
+    pub fn read_from<R: Read + Seek>(&self, reader: &mut R) -> io::Result<Cow<'_, [u8]>> {
+        self.read_range(reader, 0..self.len())
+    }
+
+    /// Read bytes `range` of the payload, reading from `reader` if it is still in the source
+    pub fn read_range<R: Read + Seek>(
+        &self,
+        reader: &mut R,
+        range: Range<u64>,
+    ) -> io::Result<Cow<'_, [u8]>> {
+        if range.start > range.end || range.end > self.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "range outside of payload",
+            ));
+        }
+
+        match self {
+            Payload::Data(data) => Ok(Cow::Borrowed(
+                &data[range.start as usize..range.end as usize],
+            )),
+            Payload::Range { offset, .. } => {
+                let mut data = vec![0; (range.end - range.start) as usize];
+                reader.seek(SeekFrom::Start(offset + range.start))?;
+                reader.read_exact(&mut data)?;
+                Ok(Cow::Owned(data))
+            }
+        }
+    }
+
+    /// Bytes `range` of the payload, `source` being the buffer the box was parsed from
+    pub fn slice<'a>(&'a self, source: &'a [u8], range: Range<u64>) -> Option<&'a [u8]> {
+        if range.start > range.end || range.end > self.len() {
+            return None;
+        }
+
+        let (data, start) = match self {
+            Payload::Data(data) => (data.as_slice(), range.start),
+            Payload::Range { offset, .. } => (source, offset + range.start),
+        };
+        let start = usize::try_from(start).ok()?;
+        let end = start.checked_add((range.end - range.start) as usize)?;
+        data.get(start..end)
+    }
+
+    /// Replace a source range with its bytes from `reader`
+    pub fn load<R: Read + Seek>(&mut self, reader: &mut R) -> io::Result<()> {
+        if let Payload::Range { .. } = self {
+            let data = self.read_from(reader)?.into_owned();
+            *self = Payload::Data(data);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn write(&self, output: &mut Vec<u8>) {
+        match self {
+            Payload::Data(data) => output.extend_from_slice(data),
+            // Ruled out by `check_loaded`
+            Payload::Range { offset, len } => panic!(
+                "payload at {}..{} is not loaded, see load_payloads",
+                offset,
+                offset + len
+            ),
+        }
+    }
+}
+
+/// Encoding details a box's fields don't capture, kept so the box writes back byte for byte
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Layout {
+    /// Bytes left in the box after its last field, e.g. padding
+    pub trailing: Vec<u8>,
+    /// The last string in the box ran to its end without a NUL terminator
+    pub unterminated: bool,
+}
+
+impl Layout {
+    // Called once every field of a box has been read
+    pub(crate) fn read(input: &[u8], state: &mut ParserState) -> Result<Self, ParseError> {
+        let unterminated = std::mem::take(&mut state.unterminated);
+        if unterminated {
+            state.warn_at(state.offset, ParseWarningKind::UnterminatedString)?;
+        }
+
+        state.field = Some("trailing");
+        let start = state.offset;
+        let trailing = read(input, state, input.len() - state.offset)?.to_vec();
+        if !trailing.is_empty() {
+            state.warn_at(
+                start,
+                ParseWarningKind::TrailingBytes(trailing.len() as u64),
+            )?;
+        }
+
+        Ok(Self {
+            trailing,
+            unterminated,
+        })
+    }
+
+    pub(crate) fn write(&self, output: &mut Vec<u8>) {
+        // Drop the NUL the string writer added
+        if self.unterminated {
+            output.pop();
+        }
+        output.extend_from_slice(&self.trailing);
+    }
+}
+
+impl fmt::Debug for Payload {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Payload::Data(data) => write!(f, "{:?}", data),
+            Payload::Range { offset, len } => write!(f, "<{}..{}>", offset, offset + len),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// Absolute byte offset in the input where the failure was detected
+    pub offset: u64,
+    /// Path of the box being parsed, e.g. `moov/trak/mdia/mdhd`
+    pub path: String,
+    /// Field being read when the failure occurred
+    pub field: Option<&'static str>,
+    pub kind: ParseErrorKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    /// Input ended before `needed` bytes could be read
+    Truncated { needed: u64, available: u64 },
+    /// A box header declared an impossible size
+    BadSize(u64),
+    /// A string field was not valid UTF-8
+    InvalidUtf8,
+    /// An entry count is larger than its box can hold
+    CountOverflow(u64),
+    /// The underlying reader failed
+    Io(io::ErrorKind),
+    /// Boxes nest deeper than `Limits::max_depth`
+    TooDeep(usize),
+    /// Entry tables or a buffered box need more than `Limits::max_alloc` bytes
+    AllocLimit(u64),
+    /// Spec violation rejected by `Strictness::Strict`
+    Nonconformant(ParseWarningKind),
+    /// A packed table declared an entry width it can't have
+    BadFieldSize(u8),
+}
+
+impl fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseErrorKind::Truncated { needed, available } => write!(
+                f,
+                "truncated input: needed {} bytes, {} available",
+                needed, available
+            ),
+            ParseErrorKind::BadSize(size) => write!(f, "bad box size {}", size),
+            ParseErrorKind::InvalidUtf8 => write!(f, "invalid utf8 string"),
+            ParseErrorKind::CountOverflow(count) => write!(f, "entry count {} overflows", count),
+            ParseErrorKind::Io(kind) => write!(f, "io error: {}", kind),
+            ParseErrorKind::TooDeep(depth) => write!(f, "boxes nested {} deep", depth),
+            ParseErrorKind::AllocLimit(size) => {
+                write!(f, "{} bytes needed, over the allocation limit", size)
+            }
+            ParseErrorKind::Nonconformant(kind) => write!(f, "{}", kind),
+            ParseErrorKind::BadFieldSize(size) => write!(f, "bad field size {} bits", size),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at offset {}", self.kind, self.offset)?;
+        if !self.path.is_empty() {
+            write!(f, " in {}", self.path)?;
+        }
+        if let Some(field) = self.field {
+            write!(f, " ({})", field)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Spec violation the lenient parser read past
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseWarning {
+    /// Absolute byte offset in the input of the violation
+    pub offset: u64,
+    /// Path of the offending box, e.g. `moov/trak/mdia/hdlr`
+    pub path: String,
+    pub kind: ParseWarningKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseWarningKind {
+    /// A string ran to the end of its box without a NUL terminator
+    UnterminatedString,
+    /// Bytes left in a box after its last field or child
+    TrailingBytes(u64),
+    /// `stsz` and `stts` disagree on the number of samples in a track
+    SampleCountMismatch { stsz: u64, stts: u64 },
+}
+
+impl fmt::Display for ParseWarningKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseWarningKind::UnterminatedString => write!(f, "string has no NUL terminator"),
+            ParseWarningKind::TrailingBytes(len) => write!(f, "{} trailing bytes", len),
+            ParseWarningKind::SampleCountMismatch { stsz, stts } => {
+                write!(f, "stsz has {} samples but stts has {}", stsz, stts)
+            }
+        }
+    }
+}
+
+impl fmt::Display for ParseWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at offset {}", self.kind, self.offset)?;
+        if !self.path.is_empty() {
+            write!(f, " in {}", self.path)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,35 @@
+// MSB-first reader for bit-packed codec configuration
+pub(crate) struct BitReader<'a> {
+    data: &'a [u8],
+    // Position in bits
+    position: usize,
+}
+
+impl<'a> BitReader<'a> {
+    pub(crate) fn new(data: &'a [u8]) -> Self {
+        Self { data, position: 0 }
+    }
+
+    pub(crate) fn remaining(&self) -> usize {
+        self.data.len() * 8 - self.position
+    }
+
+    // Read `n` <= 32 bits, `None` past the end of the data
+    pub(crate) fn read(&mut self, n: usize) -> Option<u32> {
+        if n > self.remaining() {
+            return None;
+        }
+
+        let mut value = 0u32;
+        for _ in 0..n {
+            let bit = self.data[self.position / 8] >> (7 - self.position % 8) & 1;
+            value = (value << 1) | bit as u32;
+            self.position += 1;
+        }
+        Some(value)
+    }
+
+    pub(crate) fn read_flag(&mut self) -> Option<bool> {
+        self.read(1).map(|bit| bit == 1)
+    }
+}
@@ -0,0 +1,216 @@
+use crate::base::*;
+use crate::r#macro::*;
+
+/// Track encryption box, ISO/IEC 23001-7
+#[derive(Debug)]
+pub struct BoxTenc {
+    pub header: Option<(u8, u32)>,
+    pub box_size: BoxSize,
+
+    // 1 reserved byte
+    pub _reserved: u8,
+
+    // Reserved byte in version 0, pattern encryption in version 1
+    pub _reserved1: u8,
+    pub default_crypt_byte_block: u8, // 4 bits
+    pub default_skip_byte_block: u8,  // 4 bits
+
+    pub default_is_protected: u8,
+    pub default_per_sample_iv_size: u8, // 0, 8 or 16
+    pub default_kid: [u8; 16],
+
+    // Only if protected without per-sample IVs, size is 8 bits
+    pub default_constant_iv: Option<Vec<u8>>,
+
+    pub layout: Layout,
+}
+
+impl BoxTenc {
+    fn parse_fields(
+        input: &[u8],
+        state: &mut ParserState,
+        header: &Option<(u8, u32)>,
+    ) -> Result<Self, ParseError> {
+        let version = header.map_or(0, |(version, _)| version);
+
+        let _reserved = read_field!(input state; _reserved: u8);
+        let pattern = read_field!(input state; default_crypt_byte_block: u8);
+        let (_reserved1, default_crypt_byte_block, default_skip_byte_block) = if version > 0 {
+            (0, pattern >> 4, pattern & 0x0F)
+        } else {
+            (pattern, 0, 0)
+        };
+
+        let default_is_protected = read_field!(input state; default_is_protected: u8);
+        let default_per_sample_iv_size = read_field!(input state; default_per_sample_iv_size: u8);
+        let default_kid = read_field!(input state; default_kid: [u8; 16]);
+
+        let default_constant_iv = if default_is_protected == 1 && default_per_sample_iv_size == 0 {
+            let size = read_field!(input state; default_constant_iv_size: u8);
+            state.field = Some("default_constant_iv");
+            Some(read(input, state, size as usize)?.to_vec())
+        } else {
+            None
+        };
+
+        Ok(Self {
+            header: None,
+            box_size: BoxSize::Compact,
+            _reserved,
+            _reserved1,
+            default_crypt_byte_block,
+            default_skip_byte_block,
+            default_is_protected,
+            default_per_sample_iv_size,
+            default_kid,
+            default_constant_iv,
+            layout: Layout::default(),
+        })
+    }
+
+    fn write_fields(&self, output: &mut Vec<u8>) {
+        let version = self.header.map_or(0, |(version, _)| version);
+
+        mp4box_gen! { @write output self._reserved; u8 }
+        if version > 0 {
+            output
+                .push((self.default_crypt_byte_block << 4) | (self.default_skip_byte_block & 0x0F));
+        } else {
+            output.push(self._reserved1);
+        }
+        mp4box_gen! { @write output self.default_is_protected; u8 }
+        mp4box_gen! { @write output self.default_per_sample_iv_size; u8 }
+        mp4box_gen! { @write output self.default_kid; [u8; 16] }
+
+        if let Some(iv) = &self.default_constant_iv {
+            output.push(iv.len() as u8);
+            output.extend_from_slice(iv);
+        }
+    }
+}
+
+custom_box!(BoxTenc = b"tenc", Full);
+
+/// Sample encryption box, ISO/IEC 23001-7, also PIFF's SampleEncryptionBox
+#[derive(Debug)]
+pub struct BoxSenc {
+    pub header: Option<(u8, u32)>,
+    pub box_size: BoxSize,
+
+    // PIFF override of the track encryption defaults
+    pub algorithm_id: Option<[u8; 3]>,
+    pub iv_size: Option<u8>,
+    pub kid: Option<[u8; 16]>,
+
+    pub sample_count: u32,
+    // IVs and subsample maps, their size comes from `tenc`, see `samples`
+    pub sample_data: Vec<u8>,
+
+    pub layout: Layout,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SencSample {
+    pub iv: Vec<u8>,
+    pub subsamples: Option<Vec<Subsample>>, // Only if flags & 0x000002
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Subsample {
+    pub clear_bytes: u16,
+    pub cipher_bytes: u32,
+}
+
+impl BoxSenc {
+    pub fn has_subsamples(&self) -> bool {
+        self.header.is_some_and(|(_, flags)| flags & 0x000002 != 0)
+    }
+
+    /// Samples with `iv_size` byte IVs, the `default_per_sample_iv_size` of the track's `tenc`.
+    /// A PIFF override in the box takes precedence.
+    /// `None` if the data doesn't hold `sample_count` samples of that size
+    pub fn samples(&self, iv_size: u8) -> Option<Vec<SencSample>> {
+        let data = &self.sample_data;
+        let iv_size = self.iv_size.unwrap_or(iv_size) as usize;
+        let mut position = 0;
+
+        let mut samples = Vec::with_capacity((self.sample_count as usize).min(data.len()));
+        for _ in 0..self.sample_count {
+            let iv = data.get(position..position + iv_size)?.to_vec();
+            position += iv_size;
+
+            let subsamples = if self.has_subsamples() {
+                let count = data.get(position..position + 2)?;
+                let count = u16::from_be_bytes([count[0], count[1]]);
+                position += 2;
+
+                let mut subsamples = Vec::with_capacity(count as usize);
+                for _ in 0..count {
+                    let entry = data.get(position..position + 6)?;
+                    subsamples.push(Subsample {
+                        clear_bytes: u16::from_be_bytes([entry[0], entry[1]]),
+                        cipher_bytes: u32::from_be_bytes([entry[2], entry[3], entry[4], entry[5]]),
+                    });
+                    position += 6;
+                }
+                Some(subsamples)
+            } else {
+                None
+            };
+
+            samples.push(SencSample { iv, subsamples });
+        }
+
+        (position == data.len()).then_some(samples)
+    }
+
+    fn parse_fields(
+        input: &[u8],
+        state: &mut ParserState,
+        header: &Option<(u8, u32)>,
+    ) -> Result<Self, ParseError> {
+        let flags = header.map_or(0, |(_, flags)| flags);
+
+        let (algorithm_id, iv_size, kid) = if flags & 0x000001 != 0 {
+            (
+                Some(read_field!(input state; algorithm_id: [u8; 3])),
+                Some(read_field!(input state; iv_size: u8)),
+                Some(read_field!(input state; kid: [u8; 16])),
+            )
+        } else {
+            (None, None, None)
+        };
+
+        let sample_count = read_field!(input state; sample_count: u32);
+        state.field = Some("sample_data");
+        let sample_data = read(input, state, input.len() - state.offset)?.to_vec();
+
+        Ok(Self {
+            header: None,
+            box_size: BoxSize::Compact,
+            algorithm_id,
+            iv_size,
+            kid,
+            sample_count,
+            sample_data,
+            layout: Layout::default(),
+        })
+    }
+
+    fn write_fields(&self, output: &mut Vec<u8>) {
+        if let Some(algorithm_id) = &self.algorithm_id {
+            output.extend_from_slice(algorithm_id);
+        }
+        if let Some(iv_size) = self.iv_size {
+            output.push(iv_size);
+        }
+        if let Some(kid) = &self.kid {
+            output.extend_from_slice(kid);
+        }
+
+        mp4box_gen! { @write output self.sample_count; u32 }
+        output.extend_from_slice(&self.sample_data);
+    }
+}
+
+custom_box!(BoxSenc = b"senc", Full);
@@ -0,0 +1,59 @@
+use crate::base::*;
+use crate::boxes::*;
+use crate::r#macro::Mp4BoxTrait;
+
+// Conformance checks that span more than one field, run on each box once it is parsed
+pub(crate) fn check_box(box_: &Mp4Box, state: &mut ParserState) -> Result<(), ParseError> {
+    if let Mp4Box::Stbl(stbl) = box_ {
+        let mut children = ChildChecks::default();
+        stbl.data.iter().for_each(|child| children.add(child));
+        children.check(BoxStbl::TYPE, state)?;
+    }
+
+    Ok(())
+}
+
+// What the checks need from the children of a container, for walks that don't keep them
+#[derive(Default)]
+pub(crate) struct ChildChecks {
+    stts: Option<u64>,
+    stsz: Option<u32>,
+}
+
+impl ChildChecks {
+    // Only the first box of each type counts
+    pub(crate) fn add(&mut self, box_: &Mp4Box) {
+        match box_ {
+            Mp4Box::Stts(stts) if self.stts.is_none() => {
+                let samples = stts.entries.iter().map(|entry| entry.sample_count as u64);
+                self.stts = Some(samples.sum());
+            }
+            Mp4Box::Stsz(stsz) if self.stsz.is_none() => self.stsz = Some(stsz.sample_count),
+            Mp4Box::Stz2(stz2) if self.stsz.is_none() => self.stsz = Some(stz2.sample_count),
+            _ => {}
+        }
+    }
+
+    // Run once every child of the `type_` container has been added
+    pub(crate) fn check(&self, type_: u32, state: &mut ParserState) -> Result<(), ParseError> {
+        if type_ == BoxStbl::TYPE {
+            self.check_sample_counts(state)?;
+        }
+
+        Ok(())
+    }
+
+    fn check_sample_counts(&self, state: &mut ParserState) -> Result<(), ParseError> {
+        if let (Some(stts), Some(stsz)) = (self.stts, self.stsz) {
+            if stts != stsz as u64 {
+                let kind = ParseWarningKind::SampleCountMismatch {
+                    stsz: stsz as u64,
+                    stts,
+                };
+                state.warn_at(0, kind)?;
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,129 @@
+use crate::base::*;
+use crate::r#macro::*;
+
+/// Edit list box, ISO/IEC 14496-12
+#[derive(Debug)]
+pub struct BoxElst {
+    // Version is raised to 1 on write if an entry needs 64 bits
+    pub header: Option<(u8, u32)>,
+    pub box_size: BoxSize,
+    pub entries: Vec<ElstEntry>, // Count is 32 bits
+
+    pub layout: Layout,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ElstEntry {
+    pub segment_duration: u64, // Movie timescale
+    pub media_time: i64,       // Media timescale, `EMPTY` for an empty edit
+    pub media_rate: i32,       // 16.16, integer and fraction parts
+}
+
+impl ElstEntry {
+    pub const EMPTY: i64 = -1;
+
+    pub fn is_empty_edit(&self) -> bool {
+        self.media_time == Self::EMPTY
+    }
+
+    pub fn media_rate_integer(&self) -> i16 {
+        (self.media_rate >> 16) as i16
+    }
+
+    pub fn media_rate_fraction(&self) -> i16 {
+        self.media_rate as i16
+    }
+
+    fn needs_64_bits(&self) -> bool {
+        self.segment_duration > u32::MAX as u64 || i32::try_from(self.media_time).is_err()
+    }
+}
+
+impl BoxElst {
+    /// Duration of the empty edits before the media starts, in the movie timescale
+    pub fn presentation_delay(&self) -> u64 {
+        self.entries
+            .iter()
+            .take_while(|entry| entry.is_empty_edit())
+            .map(|entry| entry.segment_duration)
+            .sum()
+    }
+
+    /// Media time the presentation starts at, in the media timescale.
+    /// For audio this skips the encoder priming samples
+    pub fn media_start(&self) -> Option<i64> {
+        self.entries
+            .iter()
+            .find(|entry| !entry.is_empty_edit())
+            .map(|entry| entry.media_time)
+    }
+
+    fn version(&self) -> u8 {
+        let version = self.header.map_or(0, |(version, _)| version);
+        if self.entries.iter().any(ElstEntry::needs_64_bits) {
+            version.max(1)
+        } else {
+            version
+        }
+    }
+
+    fn parse_fields(
+        input: &[u8],
+        state: &mut ParserState,
+        _header: &Option<(u8, u32)>,
+    ) -> Result<Self, ParseError> {
+        let (version, flags) = read_fullbox_header(input, state)?;
+        let entry_count = read_field!(input state; entry_count: u32);
+
+        state.field = Some("entries");
+        let entries = read_entries(input, state, entry_count as u64, |input, state| {
+            let (segment_duration, media_time) = if version == 1 {
+                (
+                    read_field!(input state; segment_duration: u64),
+                    read_field!(input state; media_time: i64),
+                )
+            } else {
+                (
+                    read_field!(input state; segment_duration: u32) as u64,
+                    read_field!(input state; media_time: i32) as i64,
+                )
+            };
+            let media_rate = read_field!(input state; media_rate: i32);
+
+            Ok(ElstEntry {
+                segment_duration,
+                media_time,
+                media_rate,
+            })
+        })?;
+
+        Ok(Self {
+            header: Some((version, flags)),
+            box_size: BoxSize::Compact,
+            entries,
+            layout: Layout::default(),
+        })
+    }
+
+    fn write_fields(&self, output: &mut Vec<u8>) {
+        let version = self.version();
+        let flags = self.header.map_or(0, |(_, flags)| flags);
+        output.push(version);
+        output.extend_from_slice(&u32::to_be_bytes(flags)[1..]);
+
+        output.extend_from_slice(&u32::to_be_bytes(self.entries.len() as u32));
+        for entry in &self.entries {
+            if version == 1 {
+                output.extend_from_slice(&u64::to_be_bytes(entry.segment_duration));
+                output.extend_from_slice(&i64::to_be_bytes(entry.media_time));
+            } else {
+                output.extend_from_slice(&u32::to_be_bytes(entry.segment_duration as u32));
+                output.extend_from_slice(&i32::to_be_bytes(entry.media_time as i32));
+            }
+            output.extend_from_slice(&i32::to_be_bytes(entry.media_rate));
+        }
+    }
+}
+
+// Full box header is handled by the fields so the version can follow the entries
+custom_box!(BoxElst = b"elst");
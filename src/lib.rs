@@ -1,12 +1,49 @@
+#[cfg(feature = "tokio")]
+mod async_io;
+mod audio;
 #[allow(unused_variables)]
 #[allow(unused_imports)]
 pub mod base;
+mod bits;
+mod cenc;
+mod check;
+mod elst;
 pub mod r#macro;
-
-pub use base::Either;
+mod pssh;
+mod reader;
+mod sample_table;
+mod stream;
+mod stsd;
+mod video;
+mod visit;
+
+pub use base::{
+    BoxFilter, BoxSize, Either, Layout, Limits, ParseError, ParseErrorKind, ParseOptions,
+    ParseWarning, ParseWarningKind, Payload, Strictness,
+};
+pub use reader::{parse_mp4_reader, parse_mp4_reader_with};
+pub use stream::Mp4StreamParser;
+pub use visit::{visit_mp4, visit_mp4_with, BoxHeader, BoxVisitor, Visit};
+
+#[cfg(feature = "tokio")]
+pub use async_io::{load_payloads_async, parse_mp4_async, parse_mp4_async_with, write_mp4_async};
 
 pub mod boxes {
-    use crate::r#macro::mp4box_gen;
+    use crate::r#macro::{mp4box_gen, uuid_gen};
+
+    pub use crate::audio::{
+        AudioSpecificConfig, BoxChnl, BoxDac3, BoxDac4, BoxDec3, BoxDfla, BoxDops, BoxEsds,
+        ChannelMappingTable, DecoderConfigDescriptor, Descriptor, DescriptorData, Ec3Substream,
+        EsDescriptor, FlacBlockData, FlacMetadataBlock, FlacStreamInfo, SpeakerPosition,
+    };
+    pub use crate::cenc::{BoxSenc, BoxTenc, SencSample, Subsample};
+    pub use crate::elst::{BoxElst, ElstEntry};
+    pub use crate::pssh::{BoxPssh, PlayReadyObject, PlayReadyRecord, WidevinePssh, WrmHeader};
+    pub use crate::sample_table::{BoxStz2, SampleDependency};
+    pub use crate::stsd::{
+        AudioSampleEntry, BoxStsd, GenericSampleEntry, SampleEntry, VisualSampleEntry,
+    };
+    pub use crate::video::{AvccExt, BoxAv1c, BoxAvcc, BoxHvcc, BoxVpcc, HvccArray};
 
     mp4box_gen! { version flags;
         Moof : Container,
@@ -25,24 +62,16 @@ pub mod boxes {
         Tfdt : Full {
             base_media_decode_time: u64,
         },
-        Senc : Full {
-            sample_count: u32,
-            samples: [sample_count] {
-                iv: [u8; 8],
-                subsample_count: u16 [if flags & 0x000002 != 0],
-                subsamples: [subsample_count.unwrap()] {
-                    clear_bytes: u16,
-                    cipher_bytes: u32,
-                } [if flags & 0x000002 != 0]
-            }
-        },
+        Senc : Custom,
         Saiz : Full {
             aux_info_type: u32 [if flags & 0x000001 != 0],
             aux_info_type_parameter: u32 [if flags & 0x000001 != 0],
 
             default_sample_info_size: u8,
             sample_count: u32,
-            sample_info_size: u8 [if default_sample_info_size == 0]
+            sample_info_size: [sample_count] {
+                size: u8,
+            } [if default_sample_info_size == 0],
         },
         Saio : Full {
             aux_info_type: u32 [if flags & 0x000001 != 0],
@@ -68,7 +97,7 @@ pub mod boxes {
                 sample_composition_time_offset: [u32, i32] [if version == 1] [if flags & 0x000800 != 0],
             }
         },
-        Mdat : Container = u8,
+        Mdat : Skip,
         Ftyp {
             major_brand: [u8; 4],
             minor_version: u32,
@@ -150,21 +179,30 @@ pub mod boxes {
         Dinf : Container,
         Dref : Skip,
         Stbl : Container,
-        /* TODO: Implement this properly
-        Stsd : Full {
-            entry_count: u32,
-            entries: [entry_count] {
-                _size: u32,
-                format: [u8; 4], // String
-
-                // 6 reserved bytes
-                _reserved: [u8; 6],
-
-                data_reference_index: u16,
-            },
+        Stsd : Custom,
+        Avcc : Custom,
+        Hvcc : Custom,
+        Av1c : Custom,
+        Vpcc : Custom,
+        Esds : Custom,
+        Dops : Custom,
+        Dfla : Custom,
+        Dac3 : Custom,
+        Dec3 : Custom,
+        Dac4 : Custom,
+        Chnl : Custom,
+        // Protection scheme of `encv` and `enca` sample entries
+        Sinf : Container,
+        Frma {
+            data_format: [u8; 4], // Format of the entry before encryption
+        },
+        Schm : Full {
+            scheme_type: [u8; 4], // cenc, cens, cbc1 or cbcs
+            scheme_version: u32,
+            scheme_uri: String [if flags & 0x000001 != 0],
         },
-        */
-        Stsd : Skip,
+        Schi : Container,
+        Tenc : Custom,
         Stts : Full {
             entry_count: u32,
             entries: [entry_count] {
@@ -194,6 +232,49 @@ pub mod boxes {
                 offset: u32,
             },
         },
+        Co64 : Full {
+            entry_count: u32,
+            chunk_offset: [entry_count] {
+                offset: u64,
+            },
+        },
+        Stz2 : Custom,
+        Stss : Full {
+            entry_count: u32,
+            entries: [entry_count] {
+                sample_number: u32,
+            },
+        },
+        Ctts : Full {
+            entry_count: u32,
+            entries: [entry_count] {
+                sample_count: u32,
+                sample_offset: [u32, i32] [if version == 1], // i32 if version == 1, u32 if version == 0
+            },
+        },
+        Cslg : Full {
+            composition_to_dts_shift: [i32, i64] [if version == 1],
+            least_decode_to_display_delta: [i32, i64] [if version == 1],
+            greatest_decode_to_display_delta: [i32, i64] [if version == 1],
+            composition_start_time: [i32, i64] [if version == 1],
+            composition_end_time: [i32, i64] [if version == 1],
+        },
+        Stsh : Full {
+            entry_count: u32,
+            entries: [entry_count] {
+                shadowed_sample_number: u32,
+                sync_sample_number: u32,
+            },
+        },
+        // One byte per sample, count comes from `stsz`, see `dependencies`
+        Sdtp : Full {
+            sample_dependencies: Vec<u8>,
+        },
+        // Two 3-bit pads per byte, see `pad`
+        Padb : Full {
+            sample_count: u32,
+            pad_bits: Vec<u8>,
+        },
         Udta : Skip,
         Mvex : Container,
         Trex : Full {
@@ -203,40 +284,182 @@ pub mod boxes {
             default_sample_size: u32,
             default_sample_flags: u32,
         },
-        Pssh : Skip,
+        Pssh : Custom,
         Free : Skip,
-        Edts : Skip,
+        Edts : Container,
+        Elst : Custom,
         Sgpd : Skip,
         Sbgp : Skip,
+        Uuid : Custom,
+    }
+
+    uuid_gen! {
+        // PIFF 1.1 SampleEncryptionBox
+        PiffSenc = [
+            0xa2, 0x39, 0x4f, 0x52, 0x5a, 0x9b, 0x4f, 0x14, 0xa2, 0x44, 0x6c, 0x42, 0x7c, 0x64,
+            0x8d, 0xf4,
+        ] => Senc,
+        // PIFF 1.1 ProtectionSystemSpecificHeaderBox
+        PiffPssh = [
+            0xd0, 0x8a, 0x4f, 0x18, 0x10, 0xf3, 0x4a, 0x82, 0xb6, 0xc8, 0x32, 0xd8, 0xab, 0xa1,
+            0x83, 0xd3,
+        ] => Pssh,
     }
 }
 
 use base::*;
 use boxes::*;
+use r#macro::read_header;
+use std::io::{self, Read, Seek};
+use std::ops::Range;
+
+pub use boxes::{Mp4Box, Mp4BoxRef};
+
+/// Box tree from `parse_mp4_with`, with the spec violations read past in lenient mode
+#[derive(Debug)]
+pub struct ParseOutput {
+    pub boxes: Vec<Mp4Box>,
+    pub warnings: Vec<ParseWarning>,
+    /// Input dropped by `ParseOptions::recover`
+    pub skipped: Vec<Skipped>,
+}
+
+/// Top-level box or bytes `ParseOptions::recover` passed over
+#[derive(Debug)]
+pub struct Skipped {
+    pub range: Range<u64>,
+    /// Why parsing failed at the start of `range`
+    pub error: ParseError,
+}
 
-pub use boxes::Mp4Box;
+pub fn parse_mp4(input: &[u8]) -> Result<Vec<Mp4Box>, ParseError> {
+    parse_mp4_with(input, &ParseOptions::default()).map(|output| output.boxes)
+}
 
-pub fn parse_mp4(input: &[u8]) -> Vec<Mp4Box> {
-    let mut state = ParserState { offset: 0 };
+pub fn parse_mp4_with(input: &[u8], options: &ParseOptions) -> Result<ParseOutput, ParseError> {
+    let mut state = ParserState::with_options(options.clone());
     let mut boxes = vec![];
+    let mut skipped = vec![];
 
     while !is_empty(input, &state) {
-        if let Some(box_) = parse_box(input, &mut state) {
-            boxes.push(box_);
+        let start = state.offset;
+        match parse_box(input, &mut state) {
+            Ok(box_) => boxes.push(box_),
+            Err(error) if options.recover => {
+                let next = box_end(input, start).unwrap_or_else(|| resync(input, start + 1));
+                skipped.push(Skipped {
+                    range: start as u64..next as u64,
+                    error,
+                });
+
+                state.offset = next;
+                state.unterminated = false;
+            }
+            Err(err) => return Err(err),
         }
     }
 
-    boxes
+    Ok(ParseOutput {
+        boxes,
+        warnings: state.warnings,
+        skipped,
+    })
+}
+
+// End of the box at `offset` if its header is sound, so a failed box is skipped whole
+fn box_end(input: &[u8], offset: usize) -> Option<usize> {
+    let mut state = ParserState::new();
+    state.offset = offset;
+    read_header(input, &mut state).ok()?;
+    Some(state.offset)
+}
+
+// Offset of the next plausible box header from `offset` on, the end of the input if there is none
+fn resync(input: &[u8], offset: usize) -> usize {
+    (offset..input.len().saturating_sub(7))
+        .find(|&offset| is_plausible_header(&input[offset..], (input.len() - offset) as u64))
+        .unwrap_or(input.len())
+}
+
+// A known box type with a size that fits in the `available` bytes from the start of `header`
+pub(crate) fn is_plausible_header(header: &[u8], available: u64) -> bool {
+    let size = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as u64;
+    let type_ = u32::from_ne_bytes([header[4], header[5], header[6], header[7]]);
+
+    let size = match size {
+        0 => available,
+        1 if header.len() >= 16 => u64::from_be_bytes([
+            header[8], header[9], header[10], header[11], header[12], header[13], header[14],
+            header[15],
+        ]),
+        _ => size,
+    };
+    is_known_type(type_) && size >= 8 && size <= available
+}
+
+/// Parse without copying, `Mdat`, `Skip` kinds and unknown boxes borrow from `input`
+pub fn parse_mp4_ref(input: &[u8]) -> Result<Vec<Mp4BoxRef<'_>>, ParseError> {
+    let mut state = ParserState::new();
+    let mut boxes = vec![];
+
+    while !is_empty(input, &state) {
+        boxes.push(parse_box_ref(input, &mut state)?);
+    }
+
+    Ok(boxes)
 }
 
-pub fn write_mp4(boxes: &[Mp4Box]) -> Vec<u8> {
+/// Fails with `InvalidInput` if a payload is still a `Payload::Range`, see `load_payloads`
+pub fn write_mp4(boxes: &[Mp4Box]) -> io::Result<Vec<u8>> {
+    check_loaded(boxes)?;
     let mut buf = vec![];
 
     for box_ in boxes {
         box_.write(&mut buf);
     }
 
-    buf
+    Ok(buf)
+}
+
+// Writing needs every payload in memory
+pub(crate) fn check_loaded(boxes: &[Mp4Box]) -> io::Result<()> {
+    let mut next_check = vec![boxes];
+
+    while let Some(boxes) = next_check.pop() {
+        for box_ in boxes {
+            if let Some(Payload::Range { offset, len }) = box_.payload() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "payload at {}..{} is not loaded, see load_payloads",
+                        offset,
+                        offset + len
+                    ),
+                ));
+            }
+
+            next_check.extend(child_lists(box_).map(Vec::as_slice));
+        }
+    }
+
+    Ok(())
+}
+
+/// Read every `Payload::Range` in `boxes` from `reader`, so the tree can be written
+pub fn load_payloads<R: Read + Seek>(boxes: &mut [Mp4Box], reader: &mut R) -> io::Result<()> {
+    let mut next_load = vec![boxes];
+
+    while let Some(boxes) = next_load.pop() {
+        for box_ in boxes {
+            if let Some(data) = box_.payload_mut() {
+                data.load(reader)?;
+            }
+
+            next_load.extend(child_lists_mut(box_).map(Vec::as_mut_slice));
+        }
+    }
+
+    Ok(())
 }
 
 // recursive search for box_type
@@ -251,37 +474,7 @@ pub fn find_box_mut<'a>(boxes: &'a mut [Mp4Box], box_type: &'a [u8; 4]) -> Optio
                 return Some(box_);
             }
 
-            // TODO: macro-ify this part
-            match box_ {
-                Mp4Box::Moof(box_) => {
-                    next_search.push(box_.data.as_mut_slice());
-                }
-                Mp4Box::Traf(box_) => {
-                    next_search.push(box_.data.as_mut_slice());
-                }
-                Mp4Box::Moov(box_) => {
-                    next_search.push(box_.data.as_mut_slice());
-                }
-                Mp4Box::Trak(box_) => {
-                    next_search.push(box_.data.as_mut_slice());
-                }
-                Mp4Box::Mdia(box_) => {
-                    next_search.push(box_.data.as_mut_slice());
-                }
-                Mp4Box::Minf(box_) => {
-                    next_search.push(box_.data.as_mut_slice());
-                }
-                Mp4Box::Dinf(box_) => {
-                    next_search.push(box_.data.as_mut_slice());
-                }
-                Mp4Box::Stbl(box_) => {
-                    next_search.push(box_.data.as_mut_slice());
-                }
-                Mp4Box::Mvex(box_) => {
-                    next_search.push(box_.data.as_mut_slice());
-                }
-                _ => {}
-            }
+            next_search.extend(child_lists_mut(box_).map(Vec::as_mut_slice));
         }
     }
 
@@ -299,37 +492,7 @@ pub fn find_box<'a>(boxes: &'a [Mp4Box], box_type: &'a [u8; 4]) -> Option<&'a Mp
                 return Some(box_);
             }
 
-            // TODO: macro-ify this part
-            match box_ {
-                Mp4Box::Moof(box_) => {
-                    next_search.push(box_.data.as_slice());
-                }
-                Mp4Box::Traf(box_) => {
-                    next_search.push(box_.data.as_slice());
-                }
-                Mp4Box::Moov(box_) => {
-                    next_search.push(box_.data.as_slice());
-                }
-                Mp4Box::Trak(box_) => {
-                    next_search.push(box_.data.as_slice());
-                }
-                Mp4Box::Mdia(box_) => {
-                    next_search.push(box_.data.as_slice());
-                }
-                Mp4Box::Minf(box_) => {
-                    next_search.push(box_.data.as_slice());
-                }
-                Mp4Box::Dinf(box_) => {
-                    next_search.push(box_.data.as_slice());
-                }
-                Mp4Box::Stbl(box_) => {
-                    next_search.push(box_.data.as_slice());
-                }
-                Mp4Box::Mvex(box_) => {
-                    next_search.push(box_.data.as_slice());
-                }
-                _ => {}
-            }
+            next_search.extend(child_lists(box_).map(Vec::as_slice));
         }
     }
 
@@ -341,35 +504,40 @@ pub fn list_box_tree(boxes: &[Mp4Box], indent: usize) {
         let name = get_box_type(box_);
         println!("{:indent$}{}", "", name, indent = indent * 2);
 
-        match box_ {
-            Mp4Box::Moof(box_) => {
-                list_box_tree(&box_.data, indent + 1);
-            }
-            Mp4Box::Traf(box_) => {
-                list_box_tree(&box_.data, indent + 1);
-            }
-            Mp4Box::Moov(box_) => {
-                list_box_tree(&box_.data, indent + 1);
-            }
-            Mp4Box::Trak(box_) => {
-                list_box_tree(&box_.data, indent + 1);
-            }
-            Mp4Box::Mdia(box_) => {
-                list_box_tree(&box_.data, indent + 1);
-            }
-            Mp4Box::Minf(box_) => {
-                list_box_tree(&box_.data, indent + 1);
-            }
-            Mp4Box::Dinf(box_) => {
-                list_box_tree(&box_.data, indent + 1);
-            }
-            Mp4Box::Stbl(box_) => {
-                list_box_tree(&box_.data, indent + 1);
-            }
-            Mp4Box::Mvex(box_) => {
-                list_box_tree(&box_.data, indent + 1);
+        if let Some(children) = box_.children() {
+            list_box_tree(children, indent + 1);
+        }
+
+        if let Mp4Box::Stsd(stsd) = box_ {
+            for entry in &stsd.entries {
+                let format = String::from_utf8_lossy(&entry.format()).into_owned();
+                println!("{:indent$}{}", "", format, indent = (indent + 1) * 2);
+
+                if let Some(children) = entry.children() {
+                    list_box_tree(children, indent + 2);
+                }
             }
-            _ => {}
         }
     }
 }
+
+// Every list of boxes directly inside `box_`, one per sample entry for `stsd`
+fn child_lists(box_: &Mp4Box) -> impl Iterator<Item = &Vec<Mp4Box>> {
+    let entries = match box_ {
+        Mp4Box::Stsd(stsd) => stsd.entries.as_slice(),
+        _ => &[],
+    };
+    box_.children()
+        .into_iter()
+        .chain(entries.iter().filter_map(SampleEntry::children))
+}
+
+pub(crate) fn child_lists_mut(box_: &mut Mp4Box) -> impl Iterator<Item = &mut Vec<Mp4Box>> {
+    let (children, entries) = match box_ {
+        Mp4Box::Stsd(stsd) => (None, stsd.entries.as_mut_slice()),
+        box_ => (box_.children_mut(), &mut [][..]),
+    };
+    children
+        .into_iter()
+        .chain(entries.iter_mut().filter_map(SampleEntry::children_mut))
+}
@@ -5,84 +5,233 @@ use std::fmt::Debug;
 pub(crate) trait Mp4BoxTrait: Debug {
     const TYPE: u32; // Should be 4 bytes/ASCII chars
 
-    fn parse_full(input: &[u8], state: &mut ParserState) -> Self;
-    fn parse(input: &[u8], state: &mut ParserState, header: &Option<(u8, u32)>) -> Self;
+    fn parse_full(input: &[u8], state: &mut ParserState) -> Result<Self, ParseError>
+    where
+        Self: Sized;
+    fn parse(
+        input: &[u8],
+        state: &mut ParserState,
+        header: &Option<(u8, u32)>,
+    ) -> Result<Self, ParseError>
+    where
+        Self: Sized;
 
     fn write_full(&self, output: &mut Vec<u8>);
     fn write(&self, output: &mut Vec<u8>);
+
+    // Everything after the box header, mirrors `parse_full`
+    fn write_payload(&self, output: &mut Vec<u8>) {
+        self.write(output);
+    }
 }
 
-pub(crate) fn read_header<'a>(input: &'a [u8], state: &mut ParserState) -> (u32, &'a [u8]) {
-    let size = read(input, state, 4).unwrap();
+// Reads the size/type part of a box header, a `None` length runs to the end of the input
+pub(crate) fn read_box_header(
+    input: &[u8],
+    state: &mut ParserState,
+) -> Result<(u32, BoxSize, Option<u64>), ParseError> {
+    let start = state.offset;
+
+    state.field = Some("size");
+    let size = read(input, state, 4)?;
     let size = u32::from_be_bytes([size[0], size[1], size[2], size[3]]);
 
-    let type_ = read(input, state, 4).unwrap();
+    state.field = Some("type");
+    let type_ = read(input, state, 4)?;
     let type_ = u32::from_ne_bytes([type_[0], type_[1], type_[2], type_[3]]);
 
-    (type_, read(input, state, size as usize - 8).unwrap())
+    let (box_size, length) = match size {
+        0 => (BoxSize::ToEnd, None),
+        1 => {
+            state.field = Some("largesize");
+            let size = read(input, state, 8)?;
+            let size = u64::from_be_bytes([
+                size[0], size[1], size[2], size[3], size[4], size[5], size[6], size[7],
+            ]);
+
+            match size.checked_sub(16) {
+                Some(length) => (BoxSize::Large, Some(length)),
+                None => return Err(state.error_at(start, ParseErrorKind::BadSize(size))),
+            }
+        }
+        2..=7 => {
+            state.field = Some("size");
+            return Err(state.error_at(start, ParseErrorKind::BadSize(size as u64)));
+        }
+        _ => (BoxSize::Compact, Some(size as u64 - 8)),
+    };
+
+    state.field = None;
+    Ok((type_, box_size, length))
+}
+
+pub(crate) fn read_header<'a>(
+    input: &'a [u8],
+    state: &mut ParserState,
+) -> Result<(u32, &'a [u8], BoxSize), ParseError> {
+    let (type_, box_size, length) = read_box_header(input, state)?;
+
+    // Box extends to the end of the enclosing input
+    let available = input.len() - state.offset;
+    let length = length.unwrap_or(available as u64);
+
+    // Report a truncated payload against the box it belongs to
+    state.path.push(type_);
+    let data = match usize::try_from(length) {
+        Ok(length) => read(input, state, length),
+        Err(_) => Err(state.error(ParseErrorKind::Truncated {
+            needed: length,
+            available: available as u64,
+        })),
+    };
+    state.path.pop();
+
+    Ok((type_, data?, box_size))
+}
+
+pub(crate) fn write_header(output: &mut Vec<u8>, type_: u32, box_size: BoxSize, length: usize) {
+    let size = length as u64 + 8;
+    match box_size {
+        BoxSize::Compact if size <= u32::MAX as u64 => {
+            output.extend_from_slice(&u32::to_be_bytes(size as u32)); // Size
+            output.extend_from_slice(&u32::to_ne_bytes(type_)); // Type
+        }
+        BoxSize::ToEnd => {
+            output.extend_from_slice(&u32::to_be_bytes(0)); // Size
+            output.extend_from_slice(&u32::to_ne_bytes(type_)); // Type
+        }
+        // Payload doesn't fit in a 32-bit size, switch to largesize
+        _ => {
+            output.extend_from_slice(&u32::to_be_bytes(1)); // Size
+            output.extend_from_slice(&u32::to_ne_bytes(type_)); // Type
+            output.extend_from_slice(&u64::to_be_bytes(size + 8)); // Largesize
+        }
+    }
 }
 
-pub(crate) fn read_fullbox_header(input: &[u8], state: &mut ParserState) -> (u8, u32) {
-    let version = read(input, state, 1).unwrap()[0];
-    let flags = read(input, state, 3).unwrap();
+pub(crate) fn read_fullbox_header(
+    input: &[u8],
+    state: &mut ParserState,
+) -> Result<(u8, u32), ParseError> {
+    state.field = Some("version");
+    let version = read(input, state, 1)?[0];
+    state.field = Some("flags");
+    let flags = read(input, state, 3)?;
     let flags = u32::from_be_bytes([0, flags[0], flags[1], flags[2]]);
 
-    (version, flags)
+    Ok((version, flags))
+}
+
+// Allocate room for `length` entries without aborting on absurd counts,
+// charged against `Limits::max_alloc` like `read_entries`
+pub(crate) fn alloc_vec<T>(length: usize, state: &mut ParserState) -> Result<Vec<T>, ParseError> {
+    let size = (length as u64).saturating_mul(std::mem::size_of::<T>() as u64);
+    let allocated = state.allocated.saturating_add(size);
+    if allocated > state.options.limits.max_alloc {
+        return Err(state.error(ParseErrorKind::AllocLimit(allocated)));
+    }
+    state.allocated = allocated;
+
+    let mut vec = Vec::new();
+    vec.try_reserve_exact(length)
+        .map_err(|_| state.error(ParseErrorKind::CountOverflow(length as u64)))?;
+    Ok(vec)
+}
+
+// Read a table of `count` entries, declared counts are checked against `Limits`
+// once the first entry shows how many bytes an entry takes
+pub(crate) fn read_entries<T>(
+    input: &[u8],
+    state: &mut ParserState,
+    count: u64,
+    mut read_entry: impl FnMut(&[u8], &mut ParserState) -> Result<T, ParseError>,
+) -> Result<Vec<T>, ParseError> {
+    let mut vec = Vec::new();
+    if count == 0 {
+        return Ok(vec);
+    }
+
+    let start = state.offset;
+    let field = state.field;
+    let remaining = (input.len() - start) as u64;
+    vec.push(read_entry(input, state)?);
+    state.field = field;
+
+    // Entries that take no bytes are only bounded by the allocation limit
+    let limits = &state.options.limits;
+    if state.offset > start && count > remaining.saturating_mul(limits.max_entries_per_byte) {
+        return Err(state.error_at(start, ParseErrorKind::CountOverflow(count)));
+    }
+
+    let size = count.saturating_mul(std::mem::size_of::<T>() as u64);
+    let allocated = state.allocated.saturating_add(size);
+    if allocated > limits.max_alloc {
+        return Err(state.error_at(start, ParseErrorKind::AllocLimit(allocated)));
+    }
+    state.allocated = allocated;
+
+    vec.try_reserve_exact(count as usize - 1)
+        .map_err(|_| state.error_at(start, ParseErrorKind::CountOverflow(count)))?;
+    for _ in 1..count {
+        vec.push(read_entry(input, state)?);
+    }
+
+    Ok(vec)
 }
 
 macro_rules! mp4box_gen {
     // Read types
     { @read $input:ident $state:ident $header:ident; u8 } => {
-        read($input, $state, 1).unwrap()[0]
+        read($input, $state, 1)?[0]
     };
     { @read $input:ident $state:ident $header:ident; u16 } => {
         {
-            let slice = read($input, $state, 2).unwrap();
+            let slice = read($input, $state, 2)?;
             u16::from_be_bytes([slice[0], slice[1]])
         }
     };
     { @read $input:ident $state:ident $header:ident; u32 } => {
         {
-            let slice = read($input, $state, 4).unwrap();
+            let slice = read($input, $state, 4)?;
             u32::from_be_bytes([slice[0], slice[1], slice[2], slice[3]])
         }
     };
     { @read $input:ident $state:ident $header:ident; u64 } => {
         {
-            let slice = read($input, $state, 8).unwrap();
+            let slice = read($input, $state, 8)?;
             u64::from_be_bytes([slice[0], slice[1], slice[2], slice[3], slice[4], slice[5], slice[6], slice[7]])
         }
     };
     { @read $input:ident $state:ident $header:ident; i8 } => {
-        read($input, $state, 1).unwrap()[0] as i8
+        read($input, $state, 1)?[0] as i8
     };
     { @read $input:ident $state:ident $header:ident; i16 } => {
         {
-            let slice = read($input,$state, 2).unwrap();
+            let slice = read($input, $state, 2)?;
             i16::from_be_bytes([slice[0], slice[1]])
         }
     };
     { @read $input:ident $state:ident $header:ident; i32 } => {
         {
-            let slice = read($input, $state, 4).unwrap();
+            let slice = read($input, $state, 4)?;
             i32::from_be_bytes([slice[0], slice[1], slice[2], slice[3]])
         }
     };
     { @read $input:ident $state:ident $header:ident; i64 } => {
         {
-            let slice = read($input, $state, 8).unwrap();
+            let slice = read($input, $state, 8)?;
             i64::from_be_bytes([slice[0], slice[1], slice[2], slice[3], slice[4], slice[5], slice[6], slice[7]])
         }
     };
     { @read $input:ident $state:ident $header:ident; f32 } => {
         {
-            let slice = read($input, $state, 4).unwrap();
+            let slice = read($input, $state, 4)?;
             f32::from_be_bytes([slice[0], slice[1], slice[2], slice[3]])
         }
     };
     { @read $input:ident $state:ident $header:ident; f64 } => {
         {
-            let slice = read($input, $state, 8).unwrap();
+            let slice = read($input, $state, 8)?;
             f64::from_be_bytes([slice[0], slice[1], slice[2], slice[3], slice[4], slice[5], slice[6], slice[7]])
         }
     };
@@ -96,49 +245,40 @@ macro_rules! mp4box_gen {
         }
     };
     { @read $input:ident $state:ident $header:ident; Mp4Box } => {
-        parse_box($input, $state).unwrap()
+        parse_box($input, $state)?
     };
     { @read $input:ident $state:ident $header:ident; String } => {
         {
             // Read CString in utf8
             let mut string = String::new();
 
+            let start = $state.offset;
             let mut bytes = Vec::new();
-            while let Some(byte) = read($input, $state, 1) {
-                if byte[0] == 0 {
+            $state.unterminated = true;
+            while !is_empty($input, $state) {
+                let byte = read($input, $state, 1)?[0];
+                if byte == 0 {
+                    $state.unterminated = false;
                     break;
                 }
 
                 // Read utf8 character
-                match byte[0] {
-                    0..=0x7F => {
-                        // 1 byte
-                        bytes.push(byte[0]);
-                    },
-                    0xC0..=0xDF => {
-                        // 2 bytes
-                        bytes.push(byte[0]);
-                        bytes.push(read($input, $state, 1).unwrap()[0]);
-                    },
-                    0xE0..=0xEF => {
-                        // 3 bytes
-                        bytes.push(byte[0]);
-                        bytes.push(read($input, $state, 1).unwrap()[0]);
-                        bytes.push(read($input, $state, 1).unwrap()[0]);
-                    },
-                    0xF0..=0xF7 => {
-                        // 4 bytes
-                        bytes.push(byte[0]);
-                        bytes.push(read($input, $state, 1).unwrap()[0]);
-                        bytes.push(read($input, $state, 1).unwrap()[0]);
-                        bytes.push(read($input, $state, 1).unwrap()[0]);
-                    },
-                    _ => panic!("Invalid utf8 character"),
-                }
+                let continuation = match byte {
+                    0..=0x7F => 0, // 1 byte
+                    0xC0..=0xDF => 1, // 2 bytes
+                    0xE0..=0xEF => 2, // 3 bytes
+                    0xF0..=0xF7 => 3, // 4 bytes
+                    _ => return Err($state.error_at($state.offset - 1, ParseErrorKind::InvalidUtf8)),
+                };
+                bytes.push(byte);
+                bytes.extend_from_slice(read($input, $state, continuation)?);
             }
 
             // Convert to string
-            string.push_str(std::str::from_utf8(&bytes).unwrap());
+            match std::str::from_utf8(&bytes) {
+                Ok(str) => string.push_str(str),
+                Err(_) => return Err($state.error_at(start, ParseErrorKind::InvalidUtf8)),
+            }
 
             string
         }
@@ -148,7 +288,7 @@ macro_rules! mp4box_gen {
     // Generic catch-all for metastructs
     { @read $input:ident $state:ident $header:ident; $($type:tt)* } => {
         //panic!("Unsupported type: {}", stringify!($type))
-        <$($type)*>::parse($input, $state, &$header)
+        <$($type)*>::parse($input, $state, &$header)?
     };
 
     // Write Types
@@ -208,8 +348,13 @@ macro_rules! mp4box_gen {
             mp4box_gen! { @write $output entry; $type };
         }
     };
+    { @write $output:ident $($item:ident).+; &String } => {
+        $output.extend_from_slice($($item).+.as_bytes());
+        $output.push(0);
+    };
     { @write $output:ident $($item:ident).+; String } => {
         $output.extend_from_slice($($item).+.as_bytes());
+        $output.push(0);
     };
 
     // Generic catch-all for metastructs
@@ -226,7 +371,7 @@ macro_rules! mp4box_gen {
     } => {
         {
             let length = ($input.len() - $state.offset) / std::mem::size_of::<$type>();
-            let mut vec = Vec::with_capacity(length);
+            let mut vec = alloc_vec(length, $state)?;
             for _ in 0..length {
                 vec.push(mp4box_gen! { @read $input $state $header; $type });
             }
@@ -238,28 +383,18 @@ macro_rules! mp4box_gen {
         $input:ident $state:ident $header:ident;
         [Vec<$type:tt, $length:ident>],
     } => {
-        {
-            let length = $length as usize;
-            let mut vec = Vec::with_capacity(length);
-            for _ in 0..length {
-                vec.push(mp4box_gen! { @read $input $state $header; $type });
-            }
-            vec
-        }
+        read_entries($input, $state, $length as u64, |$input, $state| {
+            Ok(mp4box_gen! { @read $input $state $header; $type })
+        })?
     };
     { // vec w/ option length
         @cond read
         $input:ident $state:ident $header:ident;
         [Vec<$type:tt, Option<[$($length:tt)*]>>],
     } => {
-        {
-            let length = ($($length)*) as usize;
-            let mut vec = Vec::with_capacity(length);
-            for _ in 0..length {
-                vec.push(mp4box_gen! { @read $input $state $header; $type });
-            }
-            vec
-        }
+        read_entries($input, $state, ($($length)*) as u64, |$input, $state| {
+            Ok(mp4box_gen! { @read $input $state $header; $type })
+        })?
     };
     { // Simple type
         @cond read $($opt:ident)*;
@@ -355,8 +490,8 @@ macro_rules! mp4box_gen {
         $($rest:tt)*
     } => {
         match $($item).+ {
-            Either::A(item) => mp4box_gen! { @cond write $output item; [&$type], },
-            Either::B(item) => mp4box_gen! { @cond write $output item; [&$($btype)*], $($rest)* },
+            Either::A(item) => { mp4box_gen! { @cond write $output item; [&$type], } }
+            Either::B(item) => { mp4box_gen! { @cond write $output item; [&$($btype)*], $($rest)* } }
         }
     };
     { // Either
@@ -367,8 +502,8 @@ macro_rules! mp4box_gen {
         $($rest:tt)*
     } => {
         match $($item).+ {
-            Either::A(item) => mp4box_gen! { @cond write $output item; [$type], },
-            Either::B(item) => mp4box_gen! { @cond write $output item; [$($btype)*], $($rest)* },
+            Either::A(item) => { mp4box_gen! { @cond write $output item; [$type], } }
+            Either::B(item) => { mp4box_gen! { @cond write $output item; [$($btype)*], $($rest)* } }
         }
     };
     { // borrow Option
@@ -405,16 +540,15 @@ macro_rules! mp4box_gen {
             ],)+ // Expanded fields
         ]
     } => {
-        use $crate::base::*;
-        use $crate::r#macro::*;
-
         paste::paste! {
             #[derive(Debug)]
             pub struct [<Box $name>] {
                 pub header: Option<(u8, u32)>,
+                pub box_size: BoxSize,
                 $(
                     pub $field: $($ftype)*,
                 )+
+                pub layout: Layout,
             }
             impl [<Box $name>] {
                 const IDSTR: &[u8] = bstringify::bstringify!([<$name:lower>]);
@@ -422,21 +556,24 @@ macro_rules! mp4box_gen {
             impl Mp4BoxTrait for [<Box $name>] {
                 const TYPE: u32 = u32::from_ne_bytes([Self::IDSTR[0], Self::IDSTR[1], Self::IDSTR[2], Self::IDSTR[3]]);
 
-                fn parse_full(input: &[u8], state: &mut ParserState) -> Self {
-                    let (version, flags) = read_fullbox_header(input, state);
+                fn parse_full(input: &[u8], state: &mut ParserState) -> Result<Self, ParseError> {
+                    let (version, flags) = read_fullbox_header(input, state)?;
                     let header = Some((version, flags));
 
-                    let mut instance = Self::parse(input, state, &header);
+                    let mut instance = Self::parse(input, state, &header)?;
                     instance.header = header;
-                    instance
+                    instance.layout = Layout::read(input, state)?;
+                    Ok(instance)
                 }
 
-                fn parse(input: &[u8], state: &mut ParserState, header: &Option<(u8, u32)>) -> Self {
+                #[allow(unused_variables)]
+                fn parse(input: &[u8], state: &mut ParserState, header: &Option<(u8, u32)>) -> Result<Self, ParseError> {
                     let uwh = header.unwrap();
                     let ($version, $flags) = uwh;
 
                     // Split out into fields so they can reference each other
                     $(
+                        state.field = Some(stringify!($field));
                         let $field = mp4box_gen! {
                             @cond read input state header;
                             [$($ctype)*],
@@ -444,29 +581,33 @@ macro_rules! mp4box_gen {
                         };
                     )+
 
-                    Self {
+                    Ok(Self {
                         header: None,
+                        box_size: BoxSize::Compact,
                         $(
                             $field,
                         )+
-                    }
+                        layout: Layout::default(),
+                    })
                 }
 
                 fn write_full(&self, output: &mut Vec<u8>) {
                     let mut data = Vec::new();
-                    self.write(&mut data);
+                    self.write_payload(&mut data);
 
                     // Write header
-                    let size = data.len() as u32 + 12;
-                    output.extend_from_slice(&u32::to_be_bytes(size)); // Size
-                    output.extend_from_slice(&u32::to_ne_bytes(Self::TYPE)); // Type
+                    write_header(output, Self::TYPE, self.box_size, data.len());
+                    output.extend(data);
+                }
 
+                fn write_payload(&self, output: &mut Vec<u8>) {
                     // Version
                     let (version, flags) = self.header.unwrap();
                     output.push(version); // Version (1 byte)
                     output.extend_from_slice(&u32::to_be_bytes(flags)[1..]); // Flags (3 bytes)
 
-                    output.extend(data);
+                    self.write(output);
+                    self.layout.write(output);
                 }
 
                 fn write(&self, output: &mut Vec<u8>) {
@@ -492,15 +633,14 @@ macro_rules! mp4box_gen {
             ],)+ // Expanded fields
         ]
     } => {
-        use $crate::base::*;
-        use $crate::r#macro::*;
-
         paste::paste! {
             #[derive(Debug)]
             pub struct [<Box $name>] {
+                pub box_size: BoxSize,
                 $(
                     pub $field: $($ftype)*,
                 )+
+                pub layout: Layout,
             }
             impl [<Box $name>] {
                 const IDSTR: &[u8] = bstringify::bstringify!([<$name:lower>]);
@@ -508,13 +648,17 @@ macro_rules! mp4box_gen {
             impl Mp4BoxTrait for [<Box $name>] {
                 const TYPE: u32 = u32::from_ne_bytes([Self::IDSTR[0], Self::IDSTR[1], Self::IDSTR[2], Self::IDSTR[3]]);
 
-                fn parse_full(input: &[u8], state: &mut ParserState) -> Self {
-                    Self::parse(input, state, &None)
+                fn parse_full(input: &[u8], state: &mut ParserState) -> Result<Self, ParseError> {
+                    let mut instance = Self::parse(input, state, &None)?;
+                    instance.layout = Layout::read(input, state)?;
+                    Ok(instance)
                 }
 
-                fn parse(input: &[u8], state: &mut ParserState, _: &Option<(u8, u32)>) -> Self {
+                #[allow(unused_variables)]
+                fn parse(input: &[u8], state: &mut ParserState, header: &Option<(u8, u32)>) -> Result<Self, ParseError> {
                     // Split out into fields so they can reference each other
                     $(
+                        state.field = Some(stringify!($field));
                         let $field = mp4box_gen! {
                             @cond read input state header;
                             [$($ctype)*],
@@ -522,24 +666,29 @@ macro_rules! mp4box_gen {
                         };
                     )+
 
-                    Self {
+                    Ok(Self {
+                        box_size: BoxSize::Compact,
                         $(
                             $field,
                         )+
-                    }
+                        layout: Layout::default(),
+                    })
                 }
 
                 fn write_full(&self, output: &mut Vec<u8>) {
                     let mut data = Vec::new();
-                    self.write(&mut data);
+                    self.write_payload(&mut data);
 
                     // Write header
-                    let size = data.len() as u32 + 8;
-                    output.extend_from_slice(&u32::to_be_bytes(size)); // Size
-                    output.extend_from_slice(&u32::to_ne_bytes(Self::TYPE)); // Type
+                    write_header(output, Self::TYPE, self.box_size, data.len());
                     output.extend(data);
                 }
 
+                fn write_payload(&self, output: &mut Vec<u8>) {
+                    self.write(output);
+                    self.layout.write(output);
+                }
+
                 fn write(&self, output: &mut Vec<u8>) {
                     $(
                         mp4box_gen! {
@@ -748,12 +897,11 @@ macro_rules! mp4box_gen {
         @expand $version:ident $flags:ident;
         $name:ident Container $type:tt
     } => {
-        use $crate::base::*;
-        use $crate::r#macro::*;
-
         paste::paste! {
             pub struct [<Box $name>] {
+                pub box_size: BoxSize,
                 pub data: Vec<$type>,
+                pub layout: Layout,
             }
             impl std::fmt::Debug for [<Box $name>] {
                 fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -771,30 +919,38 @@ macro_rules! mp4box_gen {
             impl Mp4BoxTrait for [<Box $name>] {
                 const TYPE: u32 = u32::from_ne_bytes(*bstringify::bstringify!([<$name:lower>]));
 
-                fn parse_full(input: &[u8], state: &mut ParserState) -> Self {
+                fn parse_full(input: &[u8], state: &mut ParserState) -> Result<Self, ParseError> {
                     Self::parse(input, state, &None)
                 }
 
-                fn parse(input: &[u8], state: &mut ParserState, _header: &Option<(u8, u32)>) -> Self {
+                fn parse(input: &[u8], state: &mut ParserState, _header: &Option<(u8, u32)>) -> Result<Self, ParseError> {
                     let mut data = vec![];
-                    while !is_empty(input, &state) {
+                    // Anything too short for a box header is padding
+                    while input.len() - state.offset >= 8 {
                         data.push(mp4box_gen!{ @read input state _header; $type });
                     }
 
-                    Self { data }
+                    Ok(Self {
+                        box_size: BoxSize::Compact,
+                        data,
+                        layout: Layout::read(input, state)?,
+                    })
                 }
 
                 fn write_full(&self, output: &mut Vec<u8>) {
                     let mut data = Vec::new();
-                    self.write(&mut data);
+                    self.write_payload(&mut data);
 
                     // Write header
-                    let size = data.len() as u32 + 8;
-                    output.extend_from_slice(&u32::to_be_bytes(size));
-                    output.extend_from_slice(&u32::to_ne_bytes(Self::TYPE));
+                    write_header(output, Self::TYPE, self.box_size, data.len());
                     output.extend(data);
                 }
 
+                fn write_payload(&self, output: &mut Vec<u8>) {
+                    self.write(output);
+                    self.layout.write(output);
+                }
+
                 fn write(&self, output: &mut Vec<u8>) {
                     for item in &self.data {
                         mp4box_gen! { @write output item; &$type }
@@ -810,17 +966,156 @@ macro_rules! mp4box_gen {
         mp4box_gen!{@expand $version $flags; $name Container Mp4Box}
     };
 
+    // Kind dispatch for the generated Mp4Box helpers
+    { @children $box_:ident Container } => {
+        Some(&$box_.data)
+    };
+    { @children $box_:ident $($kind:ident)? } => {
+        None
+    };
+    { @children_mut $box_:ident Container } => {
+        Some(&mut $box_.data)
+    };
+    { @children_mut $box_:ident $($kind:ident)? } => {
+        None
+    };
+    { @is_container Container } => {
+        true
+    };
+    { @is_container $($kind:ident)? } => {
+        false
+    };
+    { @payload $box_:ident Skip } => {
+        Some(&$box_.data)
+    };
+    { @payload $box_:ident $($kind:ident)? } => {
+        None
+    };
+    { @payload_mut $box_:ident Skip } => {
+        Some(&mut $box_.data)
+    };
+    { @payload_mut $box_:ident $($kind:ident)? } => {
+        None
+    };
+    { @from_payload $name:ident Skip; $box_size:ident $data:ident } => {
+        paste::paste! {
+            Ok(Mp4Box::$name(Box::new([<Box $name>] {
+                box_size: $box_size,
+                data: $data,
+            })))
+        }
+    };
+    { @from_payload $name:ident $($kind:ident)?; $box_size:ident $data:ident } => {
+        Err($data)
+    };
+
+    // Borrowed box types, raw payloads become slices into the input
+    { @ref_type $name:ident Container } => {
+        paste::paste! {
+            #[derive(Debug)]
+            pub struct [<Box $name Ref>]<'a> {
+                pub box_size: BoxSize,
+                pub data: Vec<Mp4BoxRef<'a>>,
+                /// Padding after the last child
+                pub trailing: &'a [u8],
+            }
+        }
+    };
+    { @ref_type $name:ident Skip } => {
+        paste::paste! {
+            #[derive(Debug)]
+            pub struct [<Box $name Ref>]<'a> {
+                pub box_size: BoxSize,
+                pub data: &'a [u8],
+            }
+        }
+    };
+    { @ref_type $name:ident $($kind:ident)? } => {
+        paste::paste! {
+            // Nothing to borrow, shares the owned type
+            pub type [<Box $name Ref>]<'a> = Box<[<Box $name>]>;
+        }
+    };
+    { @parse_ref $name:ident Container; $type_:ident $box_size:ident $data:ident $start:ident $state:ident } => {
+        paste::paste! {
+            {
+                let saved = $state.enter($type_, $start)?;
+                let mut children = vec![];
+                let mut result = Ok(());
+                // Anything too short for a box header is padding
+                while $data.len() - $state.offset >= 8 {
+                    match parse_box_ref($data, $state) {
+                        Ok(box_) => children.push(box_),
+                        Err(err) => {
+                            result = Err(err);
+                            break;
+                        }
+                    }
+                }
+                let trailing = &$data[$state.offset..];
+                $state.leave(saved);
+
+                result.map(|_| Mp4BoxRef::$name([<Box $name Ref>] {
+                    box_size: $box_size,
+                    data: children,
+                    trailing,
+                }))
+            }
+        }
+    };
+    { @parse_ref $name:ident Skip; $type_:ident $box_size:ident $data:ident $start:ident $state:ident } => {
+        paste::paste! {
+            Ok(Mp4BoxRef::$name([<Box $name Ref>] {
+                box_size: $box_size,
+                data: $data,
+            }))
+        }
+    };
+    { @parse_ref $name:ident $($kind:ident)?; $type_:ident $box_size:ident $data:ident $start:ident $state:ident } => {
+        match parse_payload($type_, $box_size, $data, $start, $state)? {
+            Mp4Box::$name(box_) => Ok(Mp4BoxRef::$name(box_)),
+            _ => unreachable!(),
+        }
+    };
+    { @into_owned $name:ident Container; $box_:ident } => {
+        paste::paste! {
+            Mp4Box::$name(Box::new([<Box $name>] {
+                box_size: $box_.box_size,
+                data: $box_.data.into_iter().map(Mp4BoxRef::into_owned).collect(),
+                layout: Layout {
+                    trailing: $box_.trailing.to_vec(),
+                    unterminated: false,
+                },
+            }))
+        }
+    };
+    { @into_owned $name:ident Skip; $box_:ident } => {
+        paste::paste! {
+            Mp4Box::$name(Box::new([<Box $name>] {
+                box_size: $box_.box_size,
+                data: Payload::Data($box_.data.to_vec()),
+            }))
+        }
+    };
+    { @into_owned $name:ident $($kind:ident)?; $box_:ident } => {
+        Mp4Box::$name($box_)
+    };
+
+    // Hand-written box, struct and Mp4BoxTrait impl live outside the macro
+    {
+        @expand $version:ident $flags:ident;
+        $name:ident Custom
+    } => {};
+
     // Skip box
     {
         @expand $version:ident $flags:ident;
         $name:ident Skip
     } => {
-        use $crate::base::*;
-        use $crate::r#macro::*;
-
         paste::paste! {
             pub struct [<Box $name>] {
-                pub data: Vec<u8>,
+                pub box_size: BoxSize,
+                pub data: Payload,
             }
             impl std::fmt::Debug for [<Box $name>] {
                 fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -830,15 +1125,27 @@ macro_rules! mp4box_gen {
             impl Mp4BoxTrait for [<Box $name>] {
                 const TYPE: u32 = u32::from_ne_bytes(*bstringify::bstringify!([<$name:lower>]));
 
-                fn parse_full(input: &[u8], state: &mut ParserState) -> Self {
+                fn parse_full(input: &[u8], state: &mut ParserState) -> Result<Self, ParseError> {
                     Self::parse(input, state, &None)
                 }
 
-                fn parse(input: &[u8], state: &mut ParserState, _: &Option<(u8, u32)>) -> Self {
+                fn parse(input: &[u8], state: &mut ParserState, _: &Option<(u8, u32)>) -> Result<Self, ParseError> {
                     // Read all data in box into data
-                    let data = read(input, state, input.len() - state.offset).unwrap().to_vec();
+                    let offset = state.base + state.offset as u64;
+                    let data = read(input, state, input.len() - state.offset)?;
+                    let data = if state.is_lazy(Self::TYPE) {
+                        Payload::Range {
+                            offset,
+                            len: data.len() as u64,
+                        }
+                    } else {
+                        Payload::Data(data.to_vec())
+                    };
 
-                    Self { data }
+                    Ok(Self {
+                        box_size: BoxSize::Compact,
+                        data,
+                    })
                 }
 
                 fn write_full(&self, output: &mut Vec<u8>) {
@@ -846,14 +1153,12 @@ macro_rules! mp4box_gen {
                     self.write(&mut data);
 
                     // Write header
-                    let size = data.len() as u32 + 8;
-                    output.extend_from_slice(&u32::to_be_bytes(size));
-                    output.extend_from_slice(&u32::to_ne_bytes(Self::TYPE));
+                    write_header(output, Self::TYPE, self.box_size, data.len());
                     output.extend(data);
                 }
 
                 fn write(&self, output: &mut Vec<u8>) {
-                    output.extend(&self.data);
+                    self.data.write(output);
                 }
             }
         }
@@ -867,6 +1172,9 @@ macro_rules! mp4box_gen {
             ),+ $(,)?
         })?),* $(,)? // Trailing comma may be omitted
     } => {
+        use $crate::base::*;
+        use $crate::r#macro::*;
+
         $(mp4box_gen! {
             @expand $version $flags;
             $sname $($stype $($svtype)?)? $({
@@ -875,52 +1183,383 @@ macro_rules! mp4box_gen {
                 )+
             }; [])?
         })*
+        $(mp4box_gen! { @ref_type $sname $($stype)? })*
 
         paste::paste! {
             pub enum Mp4Box {
                 $( $sname(Box<[<Box $sname>]>), )*
+                // Box type not modelled by the parser, kept as raw payload
+                Unknown {
+                    box_type: [u8; 4],
+                    box_size: BoxSize,
+                    data: Payload,
+                },
             }
             impl std::fmt::Debug for Mp4Box {
                 fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
                     match self {
                         $( Mp4Box::$sname(box_) => write!(f, "{:?}", box_), )*
+                        Mp4Box::Unknown { box_type, data, .. } => {
+                            write!(f, "{}: {:?}", String::from_utf8_lossy(box_type), data)
+                        }
                     }
                 }
             }
 
             impl Mp4Box {
+                /// Boxes inside a container. Boxes inside sample entries are under
+                /// `BoxStsd::entries`, the tree helpers such as `find_box` reach both
+                #[allow(unused_variables)]
+                pub fn children(&self) -> Option<&Vec<Mp4Box>> {
+                    match self {
+                        $( Mp4Box::$sname(box_) => mp4box_gen! { @children box_ $($stype)? }, )*
+                        Mp4Box::Unknown { .. } => None,
+                    }
+                }
+
+                #[allow(unused_variables)]
+                pub fn children_mut(&mut self) -> Option<&mut Vec<Mp4Box>> {
+                    match self {
+                        $( Mp4Box::$sname(box_) => mp4box_gen! { @children_mut box_ $($stype)? }, )*
+                        Mp4Box::Unknown { .. } => None,
+                    }
+                }
+
+                #[allow(unused_variables)]
+                pub fn payload(&self) -> Option<&Payload> {
+                    match self {
+                        $( Mp4Box::$sname(box_) => mp4box_gen! { @payload box_ $($stype)? }, )*
+                        Mp4Box::Unknown { data, .. } => Some(data),
+                    }
+                }
+
+                #[allow(unused_variables)]
+                pub fn payload_mut(&mut self) -> Option<&mut Payload> {
+                    match self {
+                        $( Mp4Box::$sname(box_) => mp4box_gen! { @payload_mut box_ $($stype)? }, )*
+                        Mp4Box::Unknown { data, .. } => Some(data),
+                    }
+                }
+
                 pub(crate) fn write(&self, output: &mut Vec<u8>) {
                     match self {
                         $( Mp4Box::$sname(box_) => box_.write_full(output), )*
+                        Mp4Box::Unknown { box_type, box_size, data } => {
+                            // Write header
+                            let mut payload = Vec::new();
+                            data.write(&mut payload);
+
+                            write_header(output, u32::from_ne_bytes(*box_type), *box_size, payload.len());
+                            output.extend(payload);
+                        }
                     }
                 }
             }
 
-            pub(crate) fn parse_box(input: &[u8], state: &mut ParserState) -> Option<Mp4Box> {
-                assert!(!is_empty(input, state));
-                let data = read_header(input, state);
+            pub(crate) fn parse_box(input: &[u8], state: &mut ParserState) -> Result<Mp4Box, ParseError> {
+                let (type_, data, box_size) = read_header(input, state)?;
+                parse_payload(type_, box_size, data, state.offset - data.len(), state)
+            }
 
-                match data.0 {
+            // Parse a box payload found at `start` in the current input
+            pub(crate) fn parse_payload(type_: u32, box_size: BoxSize, data: &[u8], start: usize, state: &mut ParserState) -> Result<Mp4Box, ParseError> {
+                // Filtered out, keep it raw without looking inside
+                if !state.is_selected(type_) {
+                    let data = if state.options.lazy_mdat {
+                        Payload::Range {
+                            offset: state.base + start as u64,
+                            len: data.len() as u64,
+                        }
+                    } else {
+                        Payload::Data(data.to_vec())
+                    };
+                    return Ok(Mp4Box::Unknown {
+                        box_type: u32::to_ne_bytes(type_),
+                        box_size,
+                        data,
+                    });
+                }
+
+                match type_ {
                     $([<Box $sname>]::TYPE => {
-                        Some(Mp4Box::$sname(Box::new([<Box $sname>]::parse_full(data.1, &mut ParserState { offset: 0 }))))
+                        let saved = state.enter(type_, start)?;
+                        let box_ = [<Box $sname>]::parse_full(data, state).and_then(|mut box_| {
+                            box_.box_size = box_size;
+                            let box_ = Mp4Box::$sname(Box::new(box_));
+                            $crate::check::check_box(&box_, state)?;
+                            Ok(box_)
+                        });
+                        state.leave(saved);
+
+                        box_
                     })*
-                    _ => panic!("Unknown box type: {}", String::from_utf8_lossy(&u32::to_ne_bytes(data.0))),
+                    _ => Ok(Mp4Box::Unknown {
+                        box_type: u32::to_ne_bytes(type_),
+                        box_size,
+                        data: Payload::Data(data.to_vec()),
+                    }),
+                }
+            }
+
+            // Build a box that only holds a raw payload, hands the payload back for any other type
+            pub(crate) fn payload_box(type_: u32, box_size: BoxSize, data: Payload) -> Result<Mp4Box, Payload> {
+                match type_ {
+                    $([<Box $sname>]::TYPE => mp4box_gen! { @from_payload $sname $($stype)?; box_size data },)*
+                    _ => Ok(Mp4Box::Unknown {
+                        box_type: u32::to_ne_bytes(type_),
+                        box_size,
+                        data,
+                    }),
                 }
             }
+            pub(crate) fn is_container_type(type_: u32) -> bool {
+                match type_ {
+                    $([<Box $sname>]::TYPE => mp4box_gen! { @is_container $($stype)? },)*
+                    _ => false,
+                }
+            }
+            pub(crate) fn is_known_type(type_: u32) -> bool {
+                matches!(type_, $([<Box $sname>]::TYPE)|*)
+            }
             pub(crate) fn is_box_type(box_: &Mp4Box, type_: u32) -> bool {
                 match box_ {
-                    $(Mp4Box::$sname(_) => [<Box $sname>]::TYPE == type_),*
+                    $(Mp4Box::$sname(_) => [<Box $sname>]::TYPE == type_,)*
+                    Mp4Box::Unknown { box_type, .. } => u32::from_ne_bytes(*box_type) == type_,
                 }
             }
             pub(crate) fn get_box_type(box_: &Mp4Box) -> String {
                 let int = match box_ {
-                    $(Mp4Box::$sname(_) => [<Box $sname>]::TYPE),*
+                    $(Mp4Box::$sname(_) => [<Box $sname>]::TYPE,)*
+                    Mp4Box::Unknown { box_type, .. } => u32::from_ne_bytes(*box_type),
                 };
-                String::from_utf8(u32::to_ne_bytes(int).to_vec()).unwrap()
+                String::from_utf8_lossy(&u32::to_ne_bytes(int)).into_owned()
+            }
+
+            // Borrowed variant of Mp4Box, see `parse_mp4_ref`
+            #[derive(Debug)]
+            pub enum Mp4BoxRef<'a> {
+                $( $sname([<Box $sname Ref>]<'a>), )*
+                Unknown {
+                    box_type: [u8; 4],
+                    box_size: BoxSize,
+                    data: &'a [u8],
+                },
+            }
+
+            impl<'a> Mp4BoxRef<'a> {
+                #[allow(unused_variables)]
+                pub fn children(&self) -> Option<&Vec<Mp4BoxRef<'a>>> {
+                    match self {
+                        $( Mp4BoxRef::$sname(box_) => mp4box_gen! { @children box_ $($stype)? }, )*
+                        Mp4BoxRef::Unknown { .. } => None,
+                    }
+                }
+
+                /// Copy borrowed payloads so the tree can be edited and written
+                pub fn into_owned(self) -> Mp4Box {
+                    match self {
+                        $( Mp4BoxRef::$sname(box_) => mp4box_gen! { @into_owned $sname $($stype)?; box_ }, )*
+                        Mp4BoxRef::Unknown { box_type, box_size, data } => Mp4Box::Unknown {
+                            box_type,
+                            box_size,
+                            data: Payload::Data(data.to_vec()),
+                        },
+                    }
+                }
+            }
+
+            pub(crate) fn parse_box_ref<'a>(input: &'a [u8], state: &mut ParserState) -> Result<Mp4BoxRef<'a>, ParseError> {
+                let (type_, data, box_size) = read_header(input, state)?;
+                let start = state.offset - data.len();
+
+                match type_ {
+                    $([<Box $sname>]::TYPE => mp4box_gen! { @parse_ref $sname $($stype)?; type_ box_size data start state },)*
+                    _ => Ok(Mp4BoxRef::Unknown {
+                        box_type: u32::to_ne_bytes(type_),
+                        box_size,
+                        data,
+                    }),
+                }
+            }
+        }
+    };
+}
+
+// Registry of uuid usertypes with a typed payload, generates BoxUuid
+macro_rules! uuid_gen {
+    {
+        $($name:ident = $uuid:expr => $type:ident),* $(,)?
+    } => {
+        paste::paste! {
+            #[derive(Debug)]
+            pub struct BoxUuid {
+                pub box_size: BoxSize,
+                pub usertype: [u8; 16],
+                pub data: UuidData,
+            }
+
+            #[derive(Debug)]
+            pub enum UuidData {
+                $( $name(Box<[<Box $type>]>), )*
+                // Unregistered usertype, kept as raw payload
+                Raw(Vec<u8>),
+            }
+
+            impl BoxUuid {
+                $( pub const [<$name:snake:upper>]: [u8; 16] = $uuid; )*
+            }
+
+            impl Mp4BoxTrait for BoxUuid {
+                const TYPE: u32 = u32::from_ne_bytes(*b"uuid");
+
+                fn parse_full(input: &[u8], state: &mut ParserState) -> Result<Self, ParseError> {
+                    Self::parse(input, state, &None)
+                }
+
+                fn parse(input: &[u8], state: &mut ParserState, _header: &Option<(u8, u32)>) -> Result<Self, ParseError> {
+                    state.field = Some("usertype");
+                    let usertype = mp4box_gen! { @read input state _header; [u8; 16] };
+
+                    // Registered payloads share the layout of the box they map onto
+                    let data = match usertype {
+                        $( Self::[<$name:snake:upper>] => {
+                            UuidData::$name(Box::new([<Box $type>]::parse_full(input, state)?))
+                        } )*
+                        _ => {
+                            state.field = Some("data");
+                            UuidData::Raw(read(input, state, input.len() - state.offset)?.to_vec())
+                        }
+                    };
+
+                    Ok(Self {
+                        box_size: BoxSize::Compact,
+                        usertype,
+                        data,
+                    })
+                }
+
+                fn write_full(&self, output: &mut Vec<u8>) {
+                    let mut data = Vec::new();
+                    self.write(&mut data);
+
+                    // Write header
+                    write_header(output, Self::TYPE, self.box_size, data.len());
+                    output.extend(data);
+                }
+
+                fn write(&self, output: &mut Vec<u8>) {
+                    output.extend_from_slice(&self.usertype);
+                    match &self.data {
+                        $( UuidData::$name(box_) => box_.write_payload(output), )*
+                        UuidData::Raw(data) => output.extend_from_slice(data),
+                    }
+                }
+            }
+        }
+    };
+}
+
+// Read one field of a hand-written box with the `mp4box_gen!` readers
+macro_rules! read_field {
+    ($input:ident $state:ident; $field:ident: $($type:tt)*) => {
+        {
+            let _header: Option<(u8, u32)> = None;
+            $state.field = Some(stringify!($field));
+            mp4box_gen! { @read $input $state _header; $($type)* }
+        }
+    };
+}
+
+// Mp4BoxTrait for a hand-written box. The struct has `box_size` and `layout` fields,
+// plus `header` when Full, and provides `parse_fields` and `write_fields`
+macro_rules! custom_box {
+    ($name:ident = $type_:expr) => {
+        impl Mp4BoxTrait for $name {
+            const TYPE: u32 = u32::from_ne_bytes(*$type_);
+
+            fn parse_full(input: &[u8], state: &mut ParserState) -> Result<Self, ParseError> {
+                let mut instance = Self::parse(input, state, &None)?;
+                instance.layout = Layout::read(input, state)?;
+                Ok(instance)
+            }
+
+            fn parse(
+                input: &[u8],
+                state: &mut ParserState,
+                header: &Option<(u8, u32)>,
+            ) -> Result<Self, ParseError> {
+                Self::parse_fields(input, state, header)
+            }
+
+            fn write_full(&self, output: &mut Vec<u8>) {
+                let mut data = Vec::new();
+                self.write_payload(&mut data);
+
+                // Write header
+                write_header(output, Self::TYPE, self.box_size, data.len());
+                output.extend(data);
+            }
+
+            fn write_payload(&self, output: &mut Vec<u8>) {
+                self.write(output);
+                self.layout.write(output);
+            }
+
+            fn write(&self, output: &mut Vec<u8>) {
+                self.write_fields(output);
+            }
+        }
+    };
+    ($name:ident = $type_:expr, Full) => {
+        impl Mp4BoxTrait for $name {
+            const TYPE: u32 = u32::from_ne_bytes(*$type_);
+
+            fn parse_full(input: &[u8], state: &mut ParserState) -> Result<Self, ParseError> {
+                let (version, flags) = read_fullbox_header(input, state)?;
+                let header = Some((version, flags));
+
+                let mut instance = Self::parse(input, state, &header)?;
+                instance.header = header;
+                instance.layout = Layout::read(input, state)?;
+                Ok(instance)
+            }
+
+            fn parse(
+                input: &[u8],
+                state: &mut ParserState,
+                header: &Option<(u8, u32)>,
+            ) -> Result<Self, ParseError> {
+                Self::parse_fields(input, state, header)
+            }
+
+            fn write_full(&self, output: &mut Vec<u8>) {
+                let mut data = Vec::new();
+                self.write_payload(&mut data);
+
+                // Write header
+                write_header(output, Self::TYPE, self.box_size, data.len());
+                output.extend(data);
+            }
+
+            fn write_payload(&self, output: &mut Vec<u8>) {
+                // Version
+                let (version, flags) = self.header.unwrap();
+                output.push(version); // Version (1 byte)
+                output.extend_from_slice(&u32::to_be_bytes(flags)[1..]); // Flags (3 bytes)
+
+                self.write(output);
+                self.layout.write(output);
+            }
+
+            fn write(&self, output: &mut Vec<u8>) {
+                self.write_fields(output);
             }
         }
     };
 }
 
 // crate visibility
+pub(crate) use custom_box;
 pub(crate) use mp4box_gen;
+pub(crate) use read_field;
+pub(crate) use uuid_gen;
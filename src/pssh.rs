@@ -0,0 +1,334 @@
+use crate::base::*;
+use crate::r#macro::*;
+
+/// Protection system specific header box, ISO/IEC 23001-7
+#[derive(Debug)]
+pub struct BoxPssh {
+    pub header: Option<(u8, u32)>,
+    pub box_size: BoxSize,
+    pub system_id: [u8; 16],
+    pub kids: Vec<[u8; 16]>, // Version 1 only, count is 32 bits
+    pub data: Vec<u8>,       // Size is 32 bits, see `widevine` and `playready`
+
+    pub layout: Layout,
+}
+
+impl BoxPssh {
+    pub const WIDEVINE: [u8; 16] = [
+        0xed, 0xef, 0x8b, 0xa9, 0x79, 0xd6, 0x4a, 0xce, 0xa3, 0xc8, 0x27, 0xdc, 0xd5, 0x1d, 0x21,
+        0xed,
+    ];
+    pub const PLAYREADY: [u8; 16] = [
+        0x9a, 0x04, 0xf0, 0x79, 0x98, 0x40, 0x42, 0x86, 0xab, 0x92, 0xe6, 0x5b, 0xe0, 0x88, 0x5f,
+        0x95,
+    ];
+
+    /// Decoded payload of a Widevine pssh, `None` for other systems or a malformed payload
+    pub fn widevine(&self) -> Option<WidevinePssh> {
+        if self.system_id != Self::WIDEVINE {
+            return None;
+        }
+        WidevinePssh::parse(&self.data)
+    }
+
+    /// Decoded payload of a PlayReady pssh, `None` for other systems or a malformed payload
+    pub fn playready(&self) -> Option<PlayReadyObject> {
+        if self.system_id != Self::PLAYREADY {
+            return None;
+        }
+        PlayReadyObject::parse(&self.data)
+    }
+
+    fn parse_fields(
+        input: &[u8],
+        state: &mut ParserState,
+        header: &Option<(u8, u32)>,
+    ) -> Result<Self, ParseError> {
+        let version = header.map_or(0, |(version, _)| version);
+        let system_id = read_field!(input state; system_id: [u8; 16]);
+
+        let kids = if version > 0 {
+            let kid_count = read_field!(input state; kid_count: u32);
+            state.field = Some("kids");
+            read_entries(input, state, kid_count as u64, |input, state| {
+                Ok(read_field!(input state; kid: [u8; 16]))
+            })?
+        } else {
+            vec![]
+        };
+
+        let data_size = read_field!(input state; data_size: u32);
+        state.field = Some("data");
+        let data = read(input, state, data_size as usize)?.to_vec();
+
+        Ok(Self {
+            header: None,
+            box_size: BoxSize::Compact,
+            system_id,
+            kids,
+            data,
+            layout: Layout::default(),
+        })
+    }
+
+    fn write_fields(&self, output: &mut Vec<u8>) {
+        let version = self.header.map_or(0, |(version, _)| version);
+        output.extend_from_slice(&self.system_id);
+
+        if version > 0 {
+            output.extend_from_slice(&u32::to_be_bytes(self.kids.len() as u32));
+            for kid in &self.kids {
+                output.extend_from_slice(kid);
+            }
+        }
+
+        output.extend_from_slice(&u32::to_be_bytes(self.data.len() as u32));
+        output.extend_from_slice(&self.data);
+    }
+}
+
+custom_box!(BoxPssh = b"pssh", Full);
+
+/// WidevinePsshData protobuf, fields this crate doesn't know are skipped
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WidevinePssh {
+    pub algorithm: Option<u32>, // 0 unencrypted, 1 AES-CTR
+    pub key_ids: Vec<[u8; 16]>,
+    pub provider: Option<String>,
+    pub content_id: Option<Vec<u8>>,
+    pub policy: Option<String>,
+    pub crypto_period_index: Option<u32>,
+    pub protection_scheme: Option<[u8; 4]>, // Fourcc such as `cenc` or `cbcs`
+}
+
+impl WidevinePssh {
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        let mut pssh = Self::default();
+        let mut position = 0;
+
+        while position < data.len() {
+            let key = read_varint(data, &mut position)?;
+            match key & 0x07 {
+                // Varint
+                0 => {
+                    let value = read_varint(data, &mut position)?;
+                    match key >> 3 {
+                        1 => pssh.algorithm = Some(value as u32),
+                        7 => pssh.crypto_period_index = Some(value as u32),
+                        9 => pssh.protection_scheme = Some((value as u32).to_be_bytes()),
+                        _ => {}
+                    }
+                }
+                // Length delimited
+                2 => {
+                    let length = read_varint(data, &mut position)?;
+                    let end = position.checked_add(usize::try_from(length).ok()?)?;
+                    let value = data.get(position..end)?;
+                    position = end;
+
+                    match key >> 3 {
+                        2 => pssh.key_ids.push(value.try_into().ok()?),
+                        3 => pssh.provider = Some(String::from_utf8(value.to_vec()).ok()?),
+                        4 => pssh.content_id = Some(value.to_vec()),
+                        6 => pssh.policy = Some(String::from_utf8(value.to_vec()).ok()?),
+                        _ => {}
+                    }
+                }
+                // Fixed 64 and 32 bits
+                1 => position += 8,
+                5 => position += 4,
+                _ => return None,
+            }
+        }
+
+        (position == data.len()).then_some(pssh)
+    }
+}
+
+fn read_varint(data: &[u8], position: &mut usize) -> Option<u64> {
+    let mut value = 0;
+    for shift in (0..64).step_by(7) {
+        let byte = *data.get(*position)?;
+        *position += 1;
+        value |= ((byte & 0x7F) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+/// PlayReady Object, a list of little-endian records
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlayReadyObject {
+    pub records: Vec<PlayReadyRecord>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlayReadyRecord {
+    pub record_type: u16,
+    pub data: Vec<u8>,
+}
+
+impl PlayReadyRecord {
+    pub const RIGHTS_MANAGEMENT_HEADER: u16 = 1;
+    pub const LICENSE_STORE: u16 = 3;
+}
+
+impl PlayReadyObject {
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        let length = u32::from_le_bytes(data.get(0..4)?.try_into().unwrap()) as usize;
+        let data = data.get(..length)?;
+        let count = u16::from_le_bytes(data.get(4..6)?.try_into().unwrap());
+
+        let mut records = vec![];
+        let mut position = 6;
+        for _ in 0..count {
+            let header = data.get(position..position + 4)?;
+            let record_type = u16::from_le_bytes([header[0], header[1]]);
+            let length = u16::from_le_bytes([header[2], header[3]]) as usize;
+            position += 4;
+
+            records.push(PlayReadyRecord {
+                record_type,
+                data: data.get(position..position + length)?.to_vec(),
+            });
+            position += length;
+        }
+
+        Some(Self { records })
+    }
+
+    /// XML of the first rights management header record, stored as UTF-16LE
+    pub fn header_xml(&self) -> Option<String> {
+        let record = self
+            .records
+            .iter()
+            .find(|record| record.record_type == PlayReadyRecord::RIGHTS_MANAGEMENT_HEADER)?;
+
+        let units = record
+            .data
+            .chunks_exact(2)
+            .map(|unit| u16::from_le_bytes([unit[0], unit[1]]));
+        let xml: String = char::decode_utf16(units).collect::<Result<_, _>>().ok()?;
+        Some(xml.trim_start_matches('\u{feff}').to_owned())
+    }
+
+    pub fn wrm_header(&self) -> Option<WrmHeader> {
+        WrmHeader::parse(&self.header_xml()?)
+    }
+}
+
+/// Fields of a PlayReady WRMHEADER, versions 4.0 to 4.3
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WrmHeader {
+    pub version: String,
+    // Big-endian like `tenc` and `pssh`, the XML stores them as little-endian GUIDs
+    pub kids: Vec<[u8; 16]>,
+    pub algorithm: Option<String>, // ALGID of the first key, e.g. AESCTR or AESCBC
+    pub la_url: Option<String>,
+    pub lui_url: Option<String>,
+    pub ds_id: Option<String>,
+}
+
+impl WrmHeader {
+    pub fn parse(xml: &str) -> Option<Self> {
+        let root = xml.find("<WRMHEADER")?;
+        let root = &xml[root..root + xml[root..].find('>')?];
+
+        let mut header = Self {
+            version: attribute(root, "version")?.to_owned(),
+            la_url: element_text(xml, "LA_URL").map(unescape),
+            lui_url: element_text(xml, "LUI_URL").map(unescape),
+            ds_id: element_text(xml, "DS_ID").map(unescape),
+            ..Self::default()
+        };
+
+        // 4.0 has KID and ALGID as elements, later versions as attributes of KID
+        for (start, _) in xml.match_indices("<KID") {
+            let tag = &xml[start..];
+            if !tag[4..].starts_with([' ', '\t', '\n', '\r', '>', '/']) {
+                continue; // <KIDS>
+            }
+            let tag = &tag[..tag.find('>')?];
+
+            let value = match attribute(tag, "VALUE") {
+                Some(value) => value,
+                None => element_text(&xml[start..], "KID")?,
+            };
+            let guid: [u8; 16] = decode_base64(value)?.try_into().ok()?;
+            header.kids.push(guid_to_kid(guid));
+
+            if header.algorithm.is_none() {
+                header.algorithm = attribute(tag, "ALGID").map(str::to_owned);
+            }
+        }
+        if header.algorithm.is_none() {
+            header.algorithm = element_text(xml, "ALGID").map(str::to_owned);
+        }
+
+        Some(header)
+    }
+}
+
+// Text of the first `<tag>` element
+fn element_text<'a>(xml: &'a str, tag: &str) -> Option<&'a str> {
+    let open = format!("<{}>", tag);
+    let start = xml.find(&open)? + open.len();
+    let end = start + xml[start..].find(&format!("</{}>", tag))?;
+    Some(xml[start..end].trim())
+}
+
+// Value of the `name` attribute in a start tag
+fn attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    ['"', '\''].into_iter().find_map(|quote| {
+        let pattern = format!(" {}={}", name, quote);
+        let start = tag.find(&pattern)? + pattern.len();
+        let end = start + tag[start..].find(quote)?;
+        Some(&tag[start..end])
+    })
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+fn decode_base64(text: &str) -> Option<Vec<u8>> {
+    let mut output = vec![];
+    let mut bits = 0u32;
+    let mut count = 0;
+
+    for byte in text
+        .bytes()
+        .filter(|byte| !byte.is_ascii_whitespace() && *byte != b'=')
+    {
+        let value = match byte {
+            b'A'..=b'Z' => byte - b'A',
+            b'a'..=b'z' => byte - b'a' + 26,
+            b'0'..=b'9' => byte - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return None,
+        };
+        bits = (bits << 6) | value as u32;
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            output.push((bits >> count) as u8);
+        }
+    }
+
+    Some(output)
+}
+
+// PlayReady GUIDs store their first three groups little-endian
+fn guid_to_kid(mut guid: [u8; 16]) -> [u8; 16] {
+    guid[0..4].reverse();
+    guid[4..6].reverse();
+    guid[6..8].reverse();
+    guid
+}
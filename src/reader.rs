@@ -0,0 +1,192 @@
+use crate::base::*;
+use crate::boxes::*;
+use crate::r#macro::*;
+use crate::{is_plausible_header, ParseOutput, Skipped};
+use std::io::{Read, Seek, SeekFrom};
+
+pub(crate) fn io_error(state: &ParserState, err: std::io::Error) -> ParseError {
+    state.error(ParseErrorKind::Io(err.kind()))
+}
+
+// Decode the header read at `offset`, returns the payload start and length
+pub(crate) fn read_box_range(
+    header: &[u8],
+    offset: u64,
+    end: u64,
+    state: &mut ParserState,
+) -> Result<(u32, BoxSize, u64, u64), ParseError> {
+    state.base = offset;
+    state.offset = 0;
+
+    let (type_, box_size, length) = read_box_header(header, state)?;
+    let start = offset + state.offset as u64;
+
+    // Box extends to the end of the file
+    let length = length.unwrap_or(end - start);
+    if length > end - start {
+        state.path.push(type_);
+        let err = state.error(ParseErrorKind::Truncated {
+            needed: length,
+            available: end - start,
+        });
+        state.path.pop();
+        return Err(err);
+    }
+
+    Ok((type_, box_size, start, length))
+}
+
+// Bytes scanned per read while looking for the next box after a bad header
+pub(crate) const RESYNC_WINDOW: u64 = 64 * 1024;
+
+// Offset of the next plausible box header in `window`, read from `window_start`.
+// Only the first `RESYNC_WINDOW` offsets are tried, the rest is the largesize of the last ones
+pub(crate) fn find_header(window: &[u8], window_start: u64, end: u64) -> Option<u64> {
+    let scanned = window.len().saturating_sub(7).min(RESYNC_WINDOW as usize);
+    (0..scanned)
+        .find(|&i| is_plausible_header(&window[i..], end - window_start - i as u64))
+        .map(|i| window_start + i as u64)
+}
+
+// Window read from `offset` for `find_header`
+pub(crate) fn resync_window(offset: u64, end: u64) -> Vec<u8> {
+    vec![0; (end - offset).min(RESYNC_WINDOW + 15) as usize]
+}
+
+// Offset of the next plausible box header from `offset` on, the end of the input if there is none
+fn resync<R: Read + Seek>(
+    reader: &mut R,
+    mut offset: u64,
+    end: u64,
+    state: &ParserState,
+) -> Result<u64, ParseError> {
+    while offset < end {
+        let mut window = resync_window(offset, end);
+        reader
+            .seek(SeekFrom::Start(offset))
+            .and_then(|_| reader.read_exact(&mut window))
+            .map_err(|err| io_error(state, err))?;
+
+        if let Some(next) = find_header(&window, offset, end) {
+            return Ok(next);
+        }
+        offset += RESYNC_WINDOW;
+    }
+
+    Ok(end)
+}
+
+// Buffer for a metadata box payload starting at `start`, charged against `Limits::max_alloc`
+// like entry tables but only while the box is parsed
+pub(crate) fn payload_buffer(
+    start: u64,
+    length: u64,
+    state: &mut ParserState,
+) -> Result<Vec<u8>, ParseError> {
+    state.base = start;
+    state.offset = 0;
+
+    let mut data = alloc_vec(length as usize, state)?;
+    data.resize(length as usize, 0);
+    // Dropped once the box is parsed, only what is parsed from it stays charged
+    state.allocated -= length;
+    Ok(data)
+}
+
+/// Parse boxes from `reader`, starting at its current position.
+///
+/// Only box headers and metadata boxes are read. `Mdat`, `Skip` kinds and unknown
+/// top-level boxes keep a `Payload::Range` into the reader instead of their data.
+pub fn parse_mp4_reader<R: Read + Seek>(reader: &mut R) -> Result<Vec<Mp4Box>, ParseError> {
+    parse_mp4_reader_with(reader, &ParseOptions::default()).map(|output| output.boxes)
+}
+
+/// `parse_mp4_reader` with `options`, top-level boxes the filter rejects aren't read either.
+/// After a bad header `recover` reads ahead 64 KiB at a time to find the next box
+pub fn parse_mp4_reader_with<R: Read + Seek>(
+    reader: &mut R,
+    options: &ParseOptions,
+) -> Result<ParseOutput, ParseError> {
+    let mut state = ParserState::with_options(options.clone());
+    let mut boxes = vec![];
+    let mut skipped = vec![];
+
+    let mut offset = reader
+        .stream_position()
+        .map_err(|err| io_error(&state, err))?;
+    let end = reader
+        .seek(SeekFrom::End(0))
+        .map_err(|err| io_error(&state, err))?;
+
+    while offset < end {
+        // Largest header is size + type + largesize
+        let mut header = [0; 16];
+        let header = &mut header[..(end - offset).min(16) as usize];
+        reader
+            .seek(SeekFrom::Start(offset))
+            .and_then(|_| reader.read_exact(header))
+            .map_err(|err| io_error(&state, err))?;
+
+        let (type_, box_size, start, length) = match read_box_range(header, offset, end, &mut state)
+        {
+            Ok(range) => range,
+            Err(error) if options.recover => {
+                let next = resync(reader, offset + 1, end, &state)?;
+                skipped.push(Skipped {
+                    range: offset..next,
+                    error,
+                });
+                offset = next;
+                continue;
+            }
+            Err(err) => return Err(err),
+        };
+
+        let data = Payload::Range {
+            offset: start,
+            len: length,
+        };
+        // Checked first so filtered mdat, free and skip boxes come back unknown too
+        let payload = match state.is_selected(type_) {
+            true => payload_box(type_, box_size, data),
+            false => Err(data),
+        };
+        let box_ = match payload {
+            Ok(box_) => Ok(box_),
+            // Filtered out, left in the reader like unknown boxes
+            Err(data) if !state.is_selected(type_) => Ok(Mp4Box::Unknown {
+                box_type: type_.to_ne_bytes(),
+                box_size,
+                data,
+            }),
+            // Metadata box, read it whole and parse it in memory
+            Err(_) => payload_buffer(start, length, &mut state).and_then(|mut data| {
+                reader
+                    .seek(SeekFrom::Start(start))
+                    .and_then(|_| reader.read_exact(&mut data))
+                    .map_err(|err| io_error(&state, err))?;
+
+                parse_payload(type_, box_size, &data, 0, &mut state)
+            }),
+        };
+
+        match box_ {
+            Ok(box_) => boxes.push(box_),
+            Err(error) if options.recover && !matches!(error.kind, ParseErrorKind::Io(_)) => {
+                skipped.push(Skipped {
+                    range: offset..start + length,
+                    error,
+                });
+                state.unterminated = false;
+            }
+            Err(err) => return Err(err),
+        }
+        offset = start + length;
+    }
+
+    Ok(ParseOutput {
+        boxes,
+        warnings: state.warnings,
+        skipped,
+    })
+}
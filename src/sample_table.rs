@@ -0,0 +1,146 @@
+use crate::base::*;
+use crate::boxes::*;
+use crate::r#macro::*;
+
+/// Compact sample size box, ISO/IEC 14496-12
+#[derive(Debug)]
+pub struct BoxStz2 {
+    pub header: Option<(u8, u32)>,
+    pub box_size: BoxSize,
+
+    // 3 reserved bytes
+    pub _reserved: [u8; 3],
+
+    pub field_size: u8, // Bits per entry, 4, 8 or 16
+    pub sample_count: u32,
+    pub entry_size: Vec<u16>,
+
+    // Low nibble after an odd number of 4-bit entries
+    pub _padding: u8,
+
+    pub layout: Layout,
+}
+
+impl BoxStz2 {
+    fn parse_fields(
+        input: &[u8],
+        state: &mut ParserState,
+        _header: &Option<(u8, u32)>,
+    ) -> Result<Self, ParseError> {
+        let _reserved = read_field!(input state; _reserved: [u8; 3]);
+        let field_size = read_field!(input state; field_size: u8);
+        if !matches!(field_size, 4 | 8 | 16) {
+            let offset = state.offset - 1;
+            return Err(state.error_at(offset, ParseErrorKind::BadFieldSize(field_size)));
+        }
+        let sample_count = read_field!(input state; sample_count: u32);
+
+        // Sizes are packed, so the whole table has to be in the input
+        state.field = Some("entry_size");
+        let length = (sample_count as u64 * field_size as u64).div_ceil(8);
+        if length > (input.len() - state.offset) as u64 {
+            return Err(state.error(ParseErrorKind::Truncated {
+                needed: length,
+                available: (input.len() - state.offset) as u64,
+            }));
+        }
+        let data = read(input, state, length as usize)?;
+
+        let mut entry_size = alloc_vec(sample_count as usize, state)?;
+        let mut _padding = 0;
+        match field_size {
+            4 => {
+                for byte in data {
+                    entry_size.push((byte >> 4) as u16);
+                    entry_size.push((byte & 0x0F) as u16);
+                }
+                if sample_count % 2 == 1 {
+                    _padding = entry_size.pop().unwrap() as u8;
+                }
+            }
+            8 => entry_size.extend(data.iter().map(|&size| size as u16)),
+            _ => entry_size.extend(
+                data.chunks_exact(2)
+                    .map(|size| u16::from_be_bytes([size[0], size[1]])),
+            ),
+        }
+
+        Ok(Self {
+            header: None,
+            box_size: BoxSize::Compact,
+            _reserved,
+            field_size,
+            sample_count,
+            entry_size,
+            _padding,
+            layout: Layout::default(),
+        })
+    }
+
+    fn write_fields(&self, output: &mut Vec<u8>) {
+        mp4box_gen! { @write output self._reserved; [u8; 3] }
+        mp4box_gen! { @write output self.field_size; u8 }
+        mp4box_gen! { @write output self.sample_count; u32 }
+
+        match self.field_size {
+            4 => {
+                for pair in self.entry_size.chunks(2) {
+                    let low = pair.get(1).map_or(self._padding, |&size| size as u8);
+                    output.push(((pair[0] as u8) << 4) | (low & 0x0F));
+                }
+            }
+            8 => output.extend(self.entry_size.iter().map(|&size| size as u8)),
+            _ => {
+                for size in &self.entry_size {
+                    output.extend_from_slice(&size.to_be_bytes());
+                }
+            }
+        }
+    }
+}
+
+custom_box!(BoxStz2 = b"stz2", Full);
+
+/// Dependency flags of one sample in `sdtp`, 2 bits each
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SampleDependency {
+    pub is_leading: u8,
+    pub sample_depends_on: u8,
+    pub sample_is_depended_on: u8,
+    pub sample_has_redundancy: u8,
+}
+
+impl SampleDependency {
+    pub fn from_byte(byte: u8) -> Self {
+        Self {
+            is_leading: byte >> 6,
+            sample_depends_on: (byte >> 4) & 0x03,
+            sample_is_depended_on: (byte >> 2) & 0x03,
+            sample_has_redundancy: byte & 0x03,
+        }
+    }
+
+    pub fn to_byte(&self) -> u8 {
+        ((self.is_leading & 0x03) << 6)
+            | ((self.sample_depends_on & 0x03) << 4)
+            | ((self.sample_is_depended_on & 0x03) << 2)
+            | (self.sample_has_redundancy & 0x03)
+    }
+}
+
+impl BoxSdtp {
+    pub fn dependencies(&self) -> impl Iterator<Item = SampleDependency> + '_ {
+        self.sample_dependencies
+            .iter()
+            .map(|&byte| SampleDependency::from_byte(byte))
+    }
+}
+
+impl BoxPadb {
+    /// Padding bits at the end of `sample`, counted from 0
+    pub fn pad(&self, sample: usize) -> Option<u8> {
+        let byte = self.pad_bits.get(sample / 2)?;
+        let shift = if sample % 2 == 1 { 0 } else { 4 };
+        Some((byte >> shift) & 0x07)
+    }
+}
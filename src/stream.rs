@@ -0,0 +1,130 @@
+use crate::base::*;
+use crate::boxes::*;
+use crate::r#macro::*;
+
+/// Push parser for top-level boxes arriving in arbitrary sized chunks, e.g. live CMAF.
+///
+/// Errors are not recoverable, the failing box stays buffered. `ParseOptions::recover` is ignored.
+#[derive(Default)]
+pub struct Mp4StreamParser {
+    buffer: Vec<u8>,
+    // Absolute offset of `buffer[0]` in the stream
+    offset: u64,
+    options: ParseOptions,
+    warnings: Vec<ParseWarning>,
+}
+
+impl Mp4StreamParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_options(options: ParseOptions) -> Self {
+        Self {
+            options,
+            ..Self::default()
+        }
+    }
+
+    /// Buffer `data` and return every top-level box it completed, in stream order.
+    ///
+    /// A box that fails after others completed is left buffered, the next call reports it
+    pub fn feed(&mut self, data: &[u8]) -> Result<Vec<Mp4Box>, ParseError> {
+        // Don't keep buffering for a box that can never be parsed
+        self.box_length()?;
+        self.buffer.extend_from_slice(data);
+
+        let mut boxes = vec![];
+        loop {
+            match self.next_box() {
+                Ok(Some(box_)) => boxes.push(box_),
+                Ok(None) => return Ok(boxes),
+                Err(_) if !boxes.is_empty() => return Ok(boxes),
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    // Parse and drain the first buffered box, `None` until it is complete
+    fn next_box(&mut self) -> Result<Option<Mp4Box>, ParseError> {
+        let length = match self.box_length()? {
+            Some(length) if self.buffer.len() as u64 >= length => length as usize,
+            _ => return Ok(None),
+        };
+
+        let mut state = self.state();
+        let box_ = parse_box(&self.buffer[..length], &mut state)?;
+        self.warnings.append(&mut state.warnings);
+
+        self.buffer.drain(..length);
+        self.offset += length as u64;
+        Ok(Some(box_))
+    }
+
+    /// Bytes still needed to complete the next box.
+    /// `None` if it is a size-0 box, which only ends with the stream (see `finish`)
+    pub fn needed(&self) -> Option<u64> {
+        match self.box_length() {
+            Ok(Some(length)) => Some(length.saturating_sub(self.buffer.len() as u64)),
+            Ok(None) => None,
+            // Nothing more to wait for, `feed` reports the error
+            Err(_) => Some(0),
+        }
+    }
+
+    /// Absolute stream offset of the next unparsed byte
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Spec violations read past in lenient mode, in stream order
+    pub fn warnings(&self) -> &[ParseWarning] {
+        &self.warnings
+    }
+
+    /// End of stream, parse what is left in the buffer.
+    /// Takes `&mut self` so `warnings` still covers the last boxes
+    pub fn finish(&mut self) -> Result<Vec<Mp4Box>, ParseError> {
+        let mut state = self.state();
+
+        let mut boxes = vec![];
+        while !is_empty(&self.buffer, &state) {
+            boxes.push(parse_box(&self.buffer, &mut state)?);
+        }
+        self.warnings.append(&mut state.warnings);
+
+        self.buffer.clear();
+        self.offset += state.offset as u64;
+        Ok(boxes)
+    }
+
+    // Total length of the buffered box, the header length while that is incomplete
+    fn box_length(&self) -> Result<Option<u64>, ParseError> {
+        let buffer = &self.buffer;
+        if buffer.len() < 8 {
+            return Ok(Some(8));
+        }
+        if buffer[..4] == [0, 0, 0, 1] && buffer.len() < 16 {
+            return Ok(Some(16));
+        }
+
+        let mut state = self.state();
+        let (_, _, length) = read_box_header(buffer, &mut state)?;
+        let length = length.map(|length| length + state.offset as u64);
+
+        // The whole box is buffered before it is parsed
+        match length {
+            Some(length) if length > self.options.limits.max_alloc => {
+                Err(state.error_at(0, ParseErrorKind::AllocLimit(length)))
+            }
+            _ => Ok(length),
+        }
+    }
+
+    // State for parsing from the start of the buffer
+    fn state(&self) -> ParserState {
+        let mut state = ParserState::with_options(self.options.clone());
+        state.base = self.offset;
+        state
+    }
+}
@@ -0,0 +1,388 @@
+use crate::base::*;
+use crate::boxes::*;
+use crate::r#macro::*;
+
+// Formats whose entries follow VisualSampleEntry
+const VISUAL_FORMATS: &[&[u8; 4]] = &[
+    b"avc1", b"avc2", b"avc3", b"avc4", b"hvc1", b"hev1", b"dvh1", b"dvhe", b"dva1", b"dvav",
+    b"vvc1", b"vvi1", b"vp08", b"vp09", b"av01", b"mp4v", b"s263", b"jpeg", b"mjp2", b"encv",
+];
+
+// Formats whose entries follow AudioSampleEntry
+const AUDIO_FORMATS: &[&[u8; 4]] = &[
+    b"mp4a", b"ac-3", b"ec-3", b"ac-4", b"Opus", b"fLaC", b"alac", b"samr", b"sawb", b"mha1",
+    b"mhm1", b"ipcm", b"fpcm", b"lpcm", b"sowt", b"twos", b"enca",
+];
+
+#[derive(Debug)]
+pub struct BoxStsd {
+    pub header: Option<(u8, u32)>,
+    pub box_size: BoxSize,
+    pub entry_count: u32,
+    pub entries: Vec<SampleEntry>,
+    pub layout: Layout,
+}
+
+#[derive(Debug)]
+pub enum SampleEntry {
+    Visual(Box<VisualSampleEntry>),
+    Audio(Box<AudioSampleEntry>),
+    /// Format without a typed layout, kept raw after the common fields
+    Generic(Box<GenericSampleEntry>),
+    /// Too short for the common sample entry fields
+    Unknown {
+        format: [u8; 4],
+        box_size: BoxSize,
+        data: Vec<u8>,
+    },
+}
+
+#[derive(Debug)]
+pub struct VisualSampleEntry {
+    pub format: [u8; 4],
+    pub box_size: BoxSize,
+
+    // 6 reserved bytes
+    pub _reserved: [u8; 6],
+    pub data_reference_index: u16,
+
+    // 16 pre-defined and reserved bytes
+    pub _pre_defined: [u8; 16],
+
+    pub width: u16,
+    pub height: u16,
+    pub horizresolution: u32, // 16.16 pixels per inch
+    pub vertresolution: u32,  // 16.16 pixels per inch
+
+    // 4 reserved bytes
+    pub _reserved1: [u8; 4],
+
+    pub frame_count: u16,
+    pub compressorname: [u8; 32], // Length prefixed, see `compressor_name`
+    pub depth: u16,
+    pub _pre_defined1: i16,
+
+    pub children: Vec<Mp4Box>,
+    pub layout: Layout,
+}
+
+#[derive(Debug)]
+pub struct AudioSampleEntry {
+    pub format: [u8; 4],
+    pub box_size: BoxSize,
+
+    // 6 reserved bytes
+    pub _reserved: [u8; 6],
+    pub data_reference_index: u16,
+
+    // QuickTime sound description version, 0 in ISO files
+    pub version: u16,
+
+    // 6 reserved bytes
+    pub _reserved1: [u8; 6],
+
+    pub channelcount: u16,
+    pub samplesize: u16,
+    pub _pre_defined: u16,
+
+    // 2 reserved bytes
+    pub _reserved2: [u8; 2],
+
+    pub samplerate: u32, // 16.16 Hz
+
+    // Extra fields of QuickTime sound description versions 1 and 2
+    pub qt_fields: Vec<u8>,
+
+    pub children: Vec<Mp4Box>,
+    pub layout: Layout,
+}
+
+#[derive(Debug)]
+pub struct GenericSampleEntry {
+    pub format: [u8; 4],
+    pub box_size: BoxSize,
+
+    // 6 reserved bytes
+    pub _reserved: [u8; 6],
+    pub data_reference_index: u16,
+
+    pub data: Vec<u8>,
+}
+
+impl VisualSampleEntry {
+    pub fn compressor_name(&self) -> String {
+        let len = (self.compressorname[0] as usize).min(31);
+        String::from_utf8_lossy(&self.compressorname[1..1 + len]).into_owned()
+    }
+}
+
+impl SampleEntry {
+    pub fn format(&self) -> [u8; 4] {
+        match self {
+            SampleEntry::Visual(entry) => entry.format,
+            SampleEntry::Audio(entry) => entry.format,
+            SampleEntry::Generic(entry) => entry.format,
+            SampleEntry::Unknown { format, .. } => *format,
+        }
+    }
+
+    pub fn children(&self) -> Option<&Vec<Mp4Box>> {
+        match self {
+            SampleEntry::Visual(entry) => Some(&entry.children),
+            SampleEntry::Audio(entry) => Some(&entry.children),
+            _ => None,
+        }
+    }
+
+    pub fn children_mut(&mut self) -> Option<&mut Vec<Mp4Box>> {
+        match self {
+            SampleEntry::Visual(entry) => Some(&mut entry.children),
+            SampleEntry::Audio(entry) => Some(&mut entry.children),
+            _ => None,
+        }
+    }
+
+    /// Format before encryption, from the `sinf/frma` of an `encv` or `enca` entry
+    pub fn original_format(&self) -> Option<[u8; 4]> {
+        self.sinf()?.data.iter().find_map(|box_| match box_ {
+            Mp4Box::Frma(frma) => Some(frma.data_format),
+            _ => None,
+        })
+    }
+
+    /// Protection scheme from `sinf/schm`, e.g. `cenc` or `cbcs`
+    pub fn scheme(&self) -> Option<&BoxSchm> {
+        self.sinf()?.data.iter().find_map(|box_| match box_ {
+            Mp4Box::Schm(schm) => Some(schm.as_ref()),
+            _ => None,
+        })
+    }
+
+    /// Default encryption parameters of the track from `sinf/schi/tenc`
+    pub fn track_encryption(&self) -> Option<&BoxTenc> {
+        let schi = self.sinf()?.data.iter().find_map(|box_| match box_ {
+            Mp4Box::Schi(schi) => Some(schi.as_ref()),
+            _ => None,
+        })?;
+        schi.data.iter().find_map(|box_| match box_ {
+            Mp4Box::Tenc(tenc) => Some(tenc.as_ref()),
+            _ => None,
+        })
+    }
+
+    fn sinf(&self) -> Option<&BoxSinf> {
+        self.children()?.iter().find_map(|box_| match box_ {
+            Mp4Box::Sinf(sinf) => Some(sinf.as_ref()),
+            _ => None,
+        })
+    }
+
+    fn parse(input: &[u8], state: &mut ParserState) -> Result<Self, ParseError> {
+        let (type_, data, box_size) = read_header(input, state)?;
+        let start = state.offset - data.len();
+
+        let saved = state.enter(type_, start)?;
+        let entry = Self::parse_payload(type_.to_ne_bytes(), box_size, data, state);
+        state.leave(saved);
+
+        entry
+    }
+
+    fn parse_payload(
+        format: [u8; 4],
+        box_size: BoxSize,
+        input: &[u8],
+        state: &mut ParserState,
+    ) -> Result<Self, ParseError> {
+        if input.len() < 8 {
+            return Ok(SampleEntry::Unknown {
+                format,
+                box_size,
+                data: input.to_vec(),
+            });
+        }
+
+        let _reserved = read_field!(input state; _reserved: [u8; 6]);
+        let data_reference_index = read_field!(input state; data_reference_index: u16);
+
+        if VISUAL_FORMATS.contains(&&format) {
+            let _pre_defined = read_field!(input state; _pre_defined: [u8; 16]);
+            let width = read_field!(input state; width: u16);
+            let height = read_field!(input state; height: u16);
+            let horizresolution = read_field!(input state; horizresolution: u32);
+            let vertresolution = read_field!(input state; vertresolution: u32);
+            let _reserved1 = read_field!(input state; _reserved1: [u8; 4]);
+            let frame_count = read_field!(input state; frame_count: u16);
+            let compressorname = read_field!(input state; compressorname: [u8; 32]);
+            let depth = read_field!(input state; depth: u16);
+            let _pre_defined1 = read_field!(input state; _pre_defined1: i16);
+
+            let children = parse_children(input, state)?;
+            Ok(SampleEntry::Visual(Box::new(VisualSampleEntry {
+                format,
+                box_size,
+                _reserved,
+                data_reference_index,
+                _pre_defined,
+                width,
+                height,
+                horizresolution,
+                vertresolution,
+                _reserved1,
+                frame_count,
+                compressorname,
+                depth,
+                _pre_defined1,
+                children,
+                layout: Layout::read(input, state)?,
+            })))
+        } else if AUDIO_FORMATS.contains(&&format) {
+            let version = read_field!(input state; version: u16);
+            let _reserved1 = read_field!(input state; _reserved1: [u8; 6]);
+            let channelcount = read_field!(input state; channelcount: u16);
+            let samplesize = read_field!(input state; samplesize: u16);
+            let _pre_defined = read_field!(input state; _pre_defined: u16);
+            let _reserved2 = read_field!(input state; _reserved2: [u8; 2]);
+            let samplerate = read_field!(input state; samplerate: u32);
+
+            // QuickTime versions 1 and 2 have extra fields before the children
+            state.field = Some("qt_fields");
+            let qt_fields = match version {
+                1 => read(input, state, 16)?.to_vec(),
+                2 => read(input, state, 36)?.to_vec(),
+                _ => vec![],
+            };
+
+            let children = parse_children(input, state)?;
+            Ok(SampleEntry::Audio(Box::new(AudioSampleEntry {
+                format,
+                box_size,
+                _reserved,
+                data_reference_index,
+                version,
+                _reserved1,
+                channelcount,
+                samplesize,
+                _pre_defined,
+                _reserved2,
+                samplerate,
+                qt_fields,
+                children,
+                layout: Layout::read(input, state)?,
+            })))
+        } else {
+            state.field = Some("data");
+            let data = read(input, state, input.len() - state.offset)?.to_vec();
+            Ok(SampleEntry::Generic(Box::new(GenericSampleEntry {
+                format,
+                box_size,
+                _reserved,
+                data_reference_index,
+                data,
+            })))
+        }
+    }
+
+    fn write(&self, output: &mut Vec<u8>) {
+        let mut data = Vec::new();
+        let (format, box_size) = match self {
+            SampleEntry::Visual(entry) => {
+                mp4box_gen! { @write data entry._reserved; [u8; 6] }
+                mp4box_gen! { @write data entry.data_reference_index; u16 }
+                mp4box_gen! { @write data entry._pre_defined; [u8; 16] }
+                mp4box_gen! { @write data entry.width; u16 }
+                mp4box_gen! { @write data entry.height; u16 }
+                mp4box_gen! { @write data entry.horizresolution; u32 }
+                mp4box_gen! { @write data entry.vertresolution; u32 }
+                mp4box_gen! { @write data entry._reserved1; [u8; 4] }
+                mp4box_gen! { @write data entry.frame_count; u16 }
+                mp4box_gen! { @write data entry.compressorname; [u8; 32] }
+                mp4box_gen! { @write data entry.depth; u16 }
+                mp4box_gen! { @write data entry._pre_defined1; i16 }
+                for child in &entry.children {
+                    child.write(&mut data);
+                }
+                entry.layout.write(&mut data);
+                (entry.format, entry.box_size)
+            }
+            SampleEntry::Audio(entry) => {
+                mp4box_gen! { @write data entry._reserved; [u8; 6] }
+                mp4box_gen! { @write data entry.data_reference_index; u16 }
+                mp4box_gen! { @write data entry.version; u16 }
+                mp4box_gen! { @write data entry._reserved1; [u8; 6] }
+                mp4box_gen! { @write data entry.channelcount; u16 }
+                mp4box_gen! { @write data entry.samplesize; u16 }
+                mp4box_gen! { @write data entry._pre_defined; u16 }
+                mp4box_gen! { @write data entry._reserved2; [u8; 2] }
+                mp4box_gen! { @write data entry.samplerate; u32 }
+                data.extend_from_slice(&entry.qt_fields);
+                for child in &entry.children {
+                    child.write(&mut data);
+                }
+                entry.layout.write(&mut data);
+                (entry.format, entry.box_size)
+            }
+            SampleEntry::Generic(entry) => {
+                mp4box_gen! { @write data entry._reserved; [u8; 6] }
+                mp4box_gen! { @write data entry.data_reference_index; u16 }
+                data.extend_from_slice(&entry.data);
+                (entry.format, entry.box_size)
+            }
+            SampleEntry::Unknown {
+                format,
+                box_size,
+                data: payload,
+            } => {
+                data.extend_from_slice(payload);
+                (*format, *box_size)
+            }
+        };
+
+        // Write header
+        write_header(output, u32::from_ne_bytes(format), box_size, data.len());
+        output.extend(data);
+    }
+}
+
+// Child boxes after the fields of a sample entry, e.g. `avcC` or `esds`
+fn parse_children(input: &[u8], state: &mut ParserState) -> Result<Vec<Mp4Box>, ParseError> {
+    let mut children = vec![];
+    state.field = None;
+
+    // Anything too short for a box header is padding
+    while input.len() - state.offset >= 8 {
+        children.push(parse_box(input, state)?);
+    }
+
+    Ok(children)
+}
+
+custom_box!(BoxStsd = b"stsd", Full);
+
+impl BoxStsd {
+    fn parse_fields(
+        input: &[u8],
+        state: &mut ParserState,
+        _header: &Option<(u8, u32)>,
+    ) -> Result<Self, ParseError> {
+        let entry_count = read_field!(input state; entry_count: u32);
+
+        state.field = Some("entries");
+        let entries = read_entries(input, state, entry_count as u64, SampleEntry::parse)?;
+
+        Ok(Self {
+            header: None,
+            box_size: BoxSize::Compact,
+            entry_count,
+            entries,
+            layout: Layout::default(),
+        })
+    }
+
+    fn write_fields(&self, output: &mut Vec<u8>) {
+        mp4box_gen! { @write output self.entry_count; u32 }
+        for entry in &self.entries {
+            entry.write(output);
+        }
+    }
+}
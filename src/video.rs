@@ -0,0 +1,486 @@
+use crate::base::*;
+use crate::r#macro::*;
+
+// H.264 profiles whose avcC carries the chroma, bit depth and SPS extension fields:
+// the High, CAVLC 4:4:4, SVC and MVC families
+const AVC_EXT_PROFILES: [u8; 14] = [
+    44, 83, 86, 100, 110, 118, 122, 128, 134, 135, 138, 139, 144, 244,
+];
+
+/// AVCDecoderConfigurationRecord, ISO/IEC 14496-15
+#[derive(Debug)]
+pub struct BoxAvcc {
+    pub box_size: BoxSize,
+    pub configuration_version: u8,
+    pub profile_indication: u8,
+    pub profile_compatibility: u8,
+    pub level_indication: u8,
+
+    // Reserved bits around the packed fields, usually all set
+    pub _reserved: [u8; 2],
+
+    pub length_size_minus_one: u8, // 2 bits
+    pub sps: Vec<Vec<u8>>,         // Count is 5 bits
+    pub pps: Vec<Vec<u8>>,
+    pub ext: Option<AvccExt>,
+
+    pub layout: Layout,
+}
+
+/// Trailing avcC fields of the High, CAVLC 4:4:4, SVC and MVC profiles
+#[derive(Debug)]
+pub struct AvccExt {
+    // Reserved bits around the packed fields, usually all set
+    pub _reserved: [u8; 3],
+
+    pub chroma_format: u8,           // 2 bits
+    pub bit_depth_luma_minus8: u8,   // 3 bits
+    pub bit_depth_chroma_minus8: u8, // 3 bits
+    pub sps_ext: Vec<Vec<u8>>,
+}
+
+impl BoxAvcc {
+    /// Bytes in the length prefix of each NAL unit in a sample
+    pub fn nal_length_size(&self) -> usize {
+        self.length_size_minus_one as usize + 1
+    }
+
+    fn parse_fields(
+        input: &[u8],
+        state: &mut ParserState,
+        _header: &Option<(u8, u32)>,
+    ) -> Result<Self, ParseError> {
+        let configuration_version = read_field!(input state; configuration_version: u8);
+        let profile_indication = read_field!(input state; profile_indication: u8);
+        let profile_compatibility = read_field!(input state; profile_compatibility: u8);
+        let level_indication = read_field!(input state; level_indication: u8);
+
+        let length_size_minus_one = read_field!(input state; length_size_minus_one: u8);
+        let sps_count = read_field!(input state; sps_count: u8);
+        let sps = read_nal_units(input, state, (sps_count & 0x1F) as u64)?;
+        let pps_count = read_field!(input state; pps_count: u8);
+        let pps = read_nal_units(input, state, pps_count as u64)?;
+
+        // Often left out by older muxers even for the High profiles
+        let ext =
+            if AVC_EXT_PROFILES.contains(&profile_indication) && input.len() - state.offset >= 4 {
+                let chroma_format = read_field!(input state; chroma_format: u8);
+                let bit_depth_luma_minus8 = read_field!(input state; bit_depth_luma_minus8: u8);
+                let bit_depth_chroma_minus8 = read_field!(input state; bit_depth_chroma_minus8: u8);
+                let sps_ext_count = read_field!(input state; sps_ext_count: u8);
+
+                Some(AvccExt {
+                    _reserved: [
+                        chroma_format & 0xFC,
+                        bit_depth_luma_minus8 & 0xF8,
+                        bit_depth_chroma_minus8 & 0xF8,
+                    ],
+                    chroma_format: chroma_format & 0x03,
+                    bit_depth_luma_minus8: bit_depth_luma_minus8 & 0x07,
+                    bit_depth_chroma_minus8: bit_depth_chroma_minus8 & 0x07,
+                    sps_ext: read_nal_units(input, state, sps_ext_count as u64)?,
+                })
+            } else {
+                None
+            };
+
+        Ok(Self {
+            box_size: BoxSize::Compact,
+            configuration_version,
+            profile_indication,
+            profile_compatibility,
+            level_indication,
+            _reserved: [length_size_minus_one & 0xFC, sps_count & 0xE0],
+            length_size_minus_one: length_size_minus_one & 0x03,
+            sps,
+            pps,
+            ext,
+            layout: Layout::default(),
+        })
+    }
+
+    fn write_fields(&self, output: &mut Vec<u8>) {
+        output.push(self.configuration_version);
+        output.push(self.profile_indication);
+        output.push(self.profile_compatibility);
+        output.push(self.level_indication);
+
+        output.push(self._reserved[0] | (self.length_size_minus_one & 0x03));
+        output.push(self._reserved[1] | (self.sps.len() as u8 & 0x1F));
+        write_nal_units(output, &self.sps);
+        output.push(self.pps.len() as u8);
+        write_nal_units(output, &self.pps);
+
+        if let Some(ext) = &self.ext {
+            output.push(ext._reserved[0] | (ext.chroma_format & 0x03));
+            output.push(ext._reserved[1] | (ext.bit_depth_luma_minus8 & 0x07));
+            output.push(ext._reserved[2] | (ext.bit_depth_chroma_minus8 & 0x07));
+            output.push(ext.sps_ext.len() as u8);
+            write_nal_units(output, &ext.sps_ext);
+        }
+    }
+}
+
+custom_box!(BoxAvcc = b"avcC");
+
+/// HEVCDecoderConfigurationRecord, ISO/IEC 14496-15
+#[derive(Debug)]
+pub struct BoxHvcc {
+    pub box_size: BoxSize,
+    pub configuration_version: u8,
+    pub general_profile_space: u8, // 2 bits
+    pub general_tier_flag: bool,
+    pub general_profile_idc: u8, // 5 bits
+    pub general_profile_compatibility_flags: u32,
+    pub general_constraint_indicator_flags: u64, // 48 bits
+    pub general_level_idc: u8,
+
+    pub min_spatial_segmentation_idc: u16, // 12 bits
+    pub parallelism_type: u8,              // 2 bits
+    pub chroma_format_idc: u8,             // 2 bits
+    pub bit_depth_luma_minus8: u8,         // 3 bits
+    pub bit_depth_chroma_minus8: u8,       // 3 bits
+    pub avg_frame_rate: u16,               // Frames per 256 seconds
+    pub constant_frame_rate: u8,           // 2 bits
+    pub num_temporal_layers: u8,           // 3 bits
+    pub temporal_id_nested: bool,
+    pub length_size_minus_one: u8, // 2 bits
+
+    // Reserved bits around the packed fields, usually all set
+    pub _reserved: [u8; 5],
+
+    pub arrays: Vec<HvccArray>,
+
+    pub layout: Layout,
+}
+
+/// NAL units of one type in an hvcC
+#[derive(Debug)]
+pub struct HvccArray {
+    /// Every NAL unit of this type is in the array, none are in the samples
+    pub array_completeness: bool,
+    pub _reserved: u8,     // 1 bit
+    pub nal_unit_type: u8, // 6 bits
+    pub nal_units: Vec<Vec<u8>>,
+}
+
+impl HvccArray {
+    pub const VPS: u8 = 32;
+    pub const SPS: u8 = 33;
+    pub const PPS: u8 = 34;
+    pub const PREFIX_SEI: u8 = 39;
+    pub const SUFFIX_SEI: u8 = 40;
+}
+
+impl BoxHvcc {
+    /// Bytes in the length prefix of each NAL unit in a sample
+    pub fn nal_length_size(&self) -> usize {
+        self.length_size_minus_one as usize + 1
+    }
+
+    /// NAL units of `nal_unit_type` across all arrays, e.g. `HvccArray::SPS`
+    pub fn nal_units(&self, nal_unit_type: u8) -> impl Iterator<Item = &Vec<u8>> {
+        self.arrays
+            .iter()
+            .filter(move |array| array.nal_unit_type == nal_unit_type)
+            .flat_map(|array| &array.nal_units)
+    }
+
+    fn parse_fields(
+        input: &[u8],
+        state: &mut ParserState,
+        _header: &Option<(u8, u32)>,
+    ) -> Result<Self, ParseError> {
+        let configuration_version = read_field!(input state; configuration_version: u8);
+        let general_profile = read_field!(input state; general_profile_idc: u8);
+        let general_profile_compatibility_flags =
+            read_field!(input state; general_profile_compatibility_flags: u32);
+        let constraint_flags =
+            read_field!(input state; general_constraint_indicator_flags: [u8; 6]);
+        let general_level_idc = read_field!(input state; general_level_idc: u8);
+
+        let min_spatial_segmentation_idc =
+            read_field!(input state; min_spatial_segmentation_idc: u16);
+        let parallelism_type = read_field!(input state; parallelism_type: u8);
+        let chroma_format_idc = read_field!(input state; chroma_format_idc: u8);
+        let bit_depth_luma_minus8 = read_field!(input state; bit_depth_luma_minus8: u8);
+        let bit_depth_chroma_minus8 = read_field!(input state; bit_depth_chroma_minus8: u8);
+        let avg_frame_rate = read_field!(input state; avg_frame_rate: u16);
+        let frame_rate = read_field!(input state; constant_frame_rate: u8);
+
+        let num_arrays = read_field!(input state; num_of_arrays: u8);
+        state.field = Some("arrays");
+        let arrays = read_entries(input, state, num_arrays as u64, |input, state| {
+            let nal_unit_type = read_field!(input state; nal_unit_type: u8);
+            let num_nalus = read_field!(input state; num_nalus: u16);
+
+            Ok(HvccArray {
+                array_completeness: nal_unit_type & 0x80 != 0,
+                _reserved: (nal_unit_type >> 6) & 0x01,
+                nal_unit_type: nal_unit_type & 0x3F,
+                nal_units: read_nal_units(input, state, num_nalus as u64)?,
+            })
+        })?;
+
+        let mut general_constraint_indicator_flags = [0; 8];
+        general_constraint_indicator_flags[2..].copy_from_slice(&constraint_flags);
+
+        Ok(Self {
+            box_size: BoxSize::Compact,
+            configuration_version,
+            general_profile_space: general_profile >> 6,
+            general_tier_flag: general_profile & 0x20 != 0,
+            general_profile_idc: general_profile & 0x1F,
+            general_profile_compatibility_flags,
+            general_constraint_indicator_flags: u64::from_be_bytes(
+                general_constraint_indicator_flags,
+            ),
+            general_level_idc,
+            min_spatial_segmentation_idc: min_spatial_segmentation_idc & 0x0FFF,
+            parallelism_type: parallelism_type & 0x03,
+            chroma_format_idc: chroma_format_idc & 0x03,
+            bit_depth_luma_minus8: bit_depth_luma_minus8 & 0x07,
+            bit_depth_chroma_minus8: bit_depth_chroma_minus8 & 0x07,
+            avg_frame_rate,
+            constant_frame_rate: frame_rate >> 6,
+            num_temporal_layers: (frame_rate >> 3) & 0x07,
+            temporal_id_nested: frame_rate & 0x04 != 0,
+            length_size_minus_one: frame_rate & 0x03,
+            _reserved: [
+                (min_spatial_segmentation_idc >> 8) as u8 & 0xF0,
+                parallelism_type & 0xFC,
+                chroma_format_idc & 0xFC,
+                bit_depth_luma_minus8 & 0xF8,
+                bit_depth_chroma_minus8 & 0xF8,
+            ],
+            arrays,
+            layout: Layout::default(),
+        })
+    }
+
+    fn write_fields(&self, output: &mut Vec<u8>) {
+        output.push(self.configuration_version);
+        output.push(
+            (self.general_profile_space << 6)
+                | ((self.general_tier_flag as u8) << 5)
+                | (self.general_profile_idc & 0x1F),
+        );
+        output.extend_from_slice(&u32::to_be_bytes(self.general_profile_compatibility_flags));
+        output.extend_from_slice(&u64::to_be_bytes(self.general_constraint_indicator_flags)[2..]);
+        output.push(self.general_level_idc);
+
+        let min_spatial_segmentation_idc =
+            ((self._reserved[0] as u16) << 8) | (self.min_spatial_segmentation_idc & 0x0FFF);
+        output.extend_from_slice(&u16::to_be_bytes(min_spatial_segmentation_idc));
+        output.push(self._reserved[1] | (self.parallelism_type & 0x03));
+        output.push(self._reserved[2] | (self.chroma_format_idc & 0x03));
+        output.push(self._reserved[3] | (self.bit_depth_luma_minus8 & 0x07));
+        output.push(self._reserved[4] | (self.bit_depth_chroma_minus8 & 0x07));
+        output.extend_from_slice(&u16::to_be_bytes(self.avg_frame_rate));
+        output.push(
+            (self.constant_frame_rate << 6)
+                | ((self.num_temporal_layers & 0x07) << 3)
+                | ((self.temporal_id_nested as u8) << 2)
+                | (self.length_size_minus_one & 0x03),
+        );
+
+        output.push(self.arrays.len() as u8);
+        for array in &self.arrays {
+            output.push(
+                ((array.array_completeness as u8) << 7)
+                    | ((array._reserved & 0x01) << 6)
+                    | (array.nal_unit_type & 0x3F),
+            );
+            output.extend_from_slice(&u16::to_be_bytes(array.nal_units.len() as u16));
+            write_nal_units(output, &array.nal_units);
+        }
+    }
+}
+
+custom_box!(BoxHvcc = b"hvcC");
+
+/// AV1CodecConfigurationRecord, AV1 Codec ISO Media File Format Binding
+#[derive(Debug)]
+pub struct BoxAv1c {
+    pub box_size: BoxSize,
+    pub marker: bool,
+    pub version: u8,         // 7 bits
+    pub seq_profile: u8,     // 3 bits
+    pub seq_level_idx_0: u8, // 5 bits
+    pub seq_tier_0: bool,
+    pub high_bitdepth: bool,
+    pub twelve_bit: bool,
+    pub monochrome: bool,
+    pub chroma_subsampling_x: bool,
+    pub chroma_subsampling_y: bool,
+    pub chroma_sample_position: u8, // 2 bits
+
+    // Reserved bits, the low 4 are only used without a presentation delay
+    pub _reserved: u8,
+
+    pub initial_presentation_delay_minus_one: Option<u8>, // 4 bits
+    pub config_obus: Vec<u8>,
+
+    pub layout: Layout,
+}
+
+impl BoxAv1c {
+    pub fn bit_depth(&self) -> u8 {
+        match (self.high_bitdepth, self.twelve_bit) {
+            (false, _) => 8,
+            (true, false) => 10,
+            (true, true) => 12,
+        }
+    }
+
+    fn parse_fields(
+        input: &[u8],
+        state: &mut ParserState,
+        _header: &Option<(u8, u32)>,
+    ) -> Result<Self, ParseError> {
+        let version = read_field!(input state; version: u8);
+        let seq_profile = read_field!(input state; seq_profile: u8);
+        let flags = read_field!(input state; seq_tier_0: u8);
+        let delay = read_field!(input state; initial_presentation_delay_minus_one: u8);
+
+        state.field = Some("config_obus");
+        let config_obus = read(input, state, input.len() - state.offset)?.to_vec();
+
+        let delay_present = delay & 0x10 != 0;
+        Ok(Self {
+            box_size: BoxSize::Compact,
+            marker: version & 0x80 != 0,
+            version: version & 0x7F,
+            seq_profile: seq_profile >> 5,
+            seq_level_idx_0: seq_profile & 0x1F,
+            seq_tier_0: flags & 0x80 != 0,
+            high_bitdepth: flags & 0x40 != 0,
+            twelve_bit: flags & 0x20 != 0,
+            monochrome: flags & 0x10 != 0,
+            chroma_subsampling_x: flags & 0x08 != 0,
+            chroma_subsampling_y: flags & 0x04 != 0,
+            chroma_sample_position: flags & 0x03,
+            _reserved: if delay_present {
+                delay & 0xE0
+            } else {
+                delay & 0xEF
+            },
+            initial_presentation_delay_minus_one: delay_present.then_some(delay & 0x0F),
+            config_obus,
+            layout: Layout::default(),
+        })
+    }
+
+    fn write_fields(&self, output: &mut Vec<u8>) {
+        output.push(((self.marker as u8) << 7) | (self.version & 0x7F));
+        output.push((self.seq_profile << 5) | (self.seq_level_idx_0 & 0x1F));
+        output.push(
+            ((self.seq_tier_0 as u8) << 7)
+                | ((self.high_bitdepth as u8) << 6)
+                | ((self.twelve_bit as u8) << 5)
+                | ((self.monochrome as u8) << 4)
+                | ((self.chroma_subsampling_x as u8) << 3)
+                | ((self.chroma_subsampling_y as u8) << 2)
+                | (self.chroma_sample_position & 0x03),
+        );
+        output.push(match self.initial_presentation_delay_minus_one {
+            Some(delay) => (self._reserved & 0xE0) | 0x10 | (delay & 0x0F),
+            None => self._reserved & 0xEF,
+        });
+        output.extend_from_slice(&self.config_obus);
+    }
+}
+
+custom_box!(BoxAv1c = b"av1C");
+
+/// VPCodecConfigurationRecord version 1, VP Codec ISO Media File Format Binding
+#[derive(Debug)]
+pub struct BoxVpcc {
+    pub header: Option<(u8, u32)>,
+    pub box_size: BoxSize,
+    pub profile: u8,
+    pub level: u8,
+    pub bit_depth: u8,          // 4 bits
+    pub chroma_subsampling: u8, // 3 bits
+    pub video_full_range_flag: bool,
+    pub colour_primaries: u8,
+    pub transfer_characteristics: u8,
+    pub matrix_coefficients: u8,
+    pub codec_initialization_data: Vec<u8>, // Size is 16 bits
+
+    pub layout: Layout,
+}
+
+impl BoxVpcc {
+    fn parse_fields(
+        input: &[u8],
+        state: &mut ParserState,
+        _header: &Option<(u8, u32)>,
+    ) -> Result<Self, ParseError> {
+        let profile = read_field!(input state; profile: u8);
+        let level = read_field!(input state; level: u8);
+        let bit_depth = read_field!(input state; bit_depth: u8);
+        let colour_primaries = read_field!(input state; colour_primaries: u8);
+        let transfer_characteristics = read_field!(input state; transfer_characteristics: u8);
+        let matrix_coefficients = read_field!(input state; matrix_coefficients: u8);
+
+        let size = read_field!(input state; codec_initialization_data_size: u16);
+        state.field = Some("codec_initialization_data");
+        let codec_initialization_data = read(input, state, size as usize)?.to_vec();
+
+        Ok(Self {
+            header: None,
+            box_size: BoxSize::Compact,
+            profile,
+            level,
+            bit_depth: bit_depth >> 4,
+            chroma_subsampling: (bit_depth >> 1) & 0x07,
+            video_full_range_flag: bit_depth & 0x01 != 0,
+            colour_primaries,
+            transfer_characteristics,
+            matrix_coefficients,
+            codec_initialization_data,
+            layout: Layout::default(),
+        })
+    }
+
+    fn write_fields(&self, output: &mut Vec<u8>) {
+        output.push(self.profile);
+        output.push(self.level);
+        output.push(
+            (self.bit_depth << 4)
+                | ((self.chroma_subsampling & 0x07) << 1)
+                | self.video_full_range_flag as u8,
+        );
+        output.push(self.colour_primaries);
+        output.push(self.transfer_characteristics);
+        output.push(self.matrix_coefficients);
+        output.extend_from_slice(&u16::to_be_bytes(
+            self.codec_initialization_data.len() as u16
+        ));
+        output.extend_from_slice(&self.codec_initialization_data);
+    }
+}
+
+custom_box!(BoxVpcc = b"vpcC", Full);
+
+// Parameter set NAL units, each prefixed with a 16-bit length
+fn read_nal_units(
+    input: &[u8],
+    state: &mut ParserState,
+    count: u64,
+) -> Result<Vec<Vec<u8>>, ParseError> {
+    state.field = Some("nal_units");
+    read_entries(input, state, count, |input, state| {
+        let length = read_field!(input state; nal_unit_length: u16);
+        state.field = Some("nal_unit");
+        Ok(read(input, state, length as usize)?.to_vec())
+    })
+}
+
+fn write_nal_units(output: &mut Vec<u8>, units: &[Vec<u8>]) {
+    for unit in units {
+        output.extend_from_slice(&u16::to_be_bytes(unit.len() as u16));
+        output.extend_from_slice(unit);
+    }
+}
@@ -0,0 +1,130 @@
+use crate::base::*;
+use crate::boxes::*;
+use crate::check::ChildChecks;
+use crate::r#macro::*;
+
+/// What `visit_mp4` does after a `BoxVisitor` callback
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Visit {
+    Continue,
+    /// From `enter_box`, pass over the box without parsing it.
+    /// From `on_box` and `leave_box`, pass over the rest of the enclosing box
+    Skip,
+    /// End the walk, `visit_mp4` returns `Ok`
+    Stop,
+}
+
+/// Header of a box as passed to `BoxVisitor::enter_box`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BoxHeader {
+    pub box_type: [u8; 4],
+    pub box_size: BoxSize,
+    /// Payload length, without the header
+    pub len: u64,
+}
+
+/// Callbacks for `visit_mp4`, boxes are entered and left in file order.
+/// `path` ends with the type of the box the callback is about
+pub trait BoxVisitor {
+    /// `offset` is where the box header starts in the input
+    fn enter_box(&mut self, path: &[[u8; 4]], header: &BoxHeader, offset: u64) -> Visit {
+        let _ = (path, header, offset);
+        Visit::Continue
+    }
+
+    /// Called after everything inside the box has been visited
+    fn leave_box(&mut self, path: &[[u8; 4]]) -> Visit {
+        let _ = path;
+        Visit::Continue
+    }
+
+    /// Called with every box that isn't a container, once parsed
+    fn on_box(&mut self, path: &[[u8; 4]], box_: &Mp4Box) -> Visit {
+        let _ = (path, box_);
+        Visit::Continue
+    }
+}
+
+/// Walk the boxes in `input` without building the tree, only one leaf box is held at a time
+pub fn visit_mp4<V: BoxVisitor>(input: &[u8], visitor: &mut V) -> Result<(), ParseError> {
+    visit_mp4_with(input, visitor, &ParseOptions::default()).map(|_| ())
+}
+
+/// `visit_mp4` with `options`, returns the spec violations read past in lenient mode.
+/// `ParseOptions::recover` is ignored
+pub fn visit_mp4_with<V: BoxVisitor>(
+    input: &[u8],
+    visitor: &mut V,
+    options: &ParseOptions,
+) -> Result<Vec<ParseWarning>, ParseError> {
+    let mut state = ParserState::with_options(options.clone());
+    visit_boxes(input, &mut state, &mut vec![], visitor)?;
+    Ok(state.warnings)
+}
+
+// Visit every box in `input`, false once the visitor stopped
+fn visit_boxes<V: BoxVisitor>(
+    input: &[u8],
+    state: &mut ParserState,
+    path: &mut Vec<[u8; 4]>,
+    visitor: &mut V,
+) -> Result<bool, ParseError> {
+    let mut children = ChildChecks::default();
+    loop {
+        // Inside a container, anything too short for a box header is padding
+        let remaining = input.len() - state.offset;
+        if remaining == 0 || (!path.is_empty() && remaining < 8) {
+            // Checked like `parse_mp4` checks a container, once its children are all parsed
+            if let Some(&type_) = state.path.last() {
+                Layout::read(input, state)?;
+                children.check(type_, state)?;
+            }
+            return Ok(true);
+        }
+
+        let offset = state.base + state.offset as u64;
+        let (type_, data, box_size) = read_header(input, state)?;
+        let start = state.offset - data.len();
+
+        path.push(type_.to_ne_bytes());
+        let header = BoxHeader {
+            box_type: type_.to_ne_bytes(),
+            box_size,
+            len: data.len() as u64,
+        };
+
+        let visit = match visitor.enter_box(path, &header, offset) {
+            Visit::Continue if is_container_type(type_) && state.is_selected(type_) => {
+                let saved = state.enter(type_, start)?;
+                let visited = visit_boxes(data, state, path, visitor);
+                state.leave(saved);
+
+                if !visited? {
+                    return Ok(false);
+                }
+                visitor.leave_box(path)
+            }
+            Visit::Continue => {
+                let box_ = parse_payload(type_, box_size, data, start, state)?;
+                children.add(&box_);
+                match visitor.on_box(path, &box_) {
+                    Visit::Stop => return Ok(false),
+                    Visit::Skip => match visitor.leave_box(path) {
+                        Visit::Stop => Visit::Stop,
+                        _ => Visit::Skip,
+                    },
+                    Visit::Continue => visitor.leave_box(path),
+                }
+            }
+            Visit::Skip => Visit::Continue,
+            Visit::Stop => Visit::Stop,
+        };
+        path.pop();
+
+        match visit {
+            Visit::Continue => {}
+            Visit::Skip => return Ok(true),
+            Visit::Stop => return Ok(false),
+        }
+    }
+}
@@ -0,0 +1,60 @@
+#![cfg(feature = "tokio")]
+
+mod common;
+
+use bmf_parse::*;
+use common::*;
+use std::io::Cursor;
+
+#[tokio::test]
+async fn async_round_trip() {
+    let input = sample_file();
+    let mut reader = Cursor::new(&input);
+    let mut boxes = parse_mp4_async(&mut reader).await.unwrap();
+
+    let mut output = vec![];
+    let err = write_mp4_async(&boxes, &mut output).await.unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+
+    load_payloads_async(&mut boxes, &mut reader).await.unwrap();
+    let mut output = vec![];
+    write_mp4_async(&boxes, &mut output).await.unwrap();
+    assert!(output == input);
+}
+
+#[tokio::test]
+async fn async_options_apply() {
+    // Trailing bytes fail moov in strict mode, recovery skips it and keeps free
+    let moov = boxed(
+        b"moov",
+        &[full(b"mvhd", 0, 0, &filler(96)), vec![0; 4]].concat(),
+    );
+    let input = [moov, boxed(b"free", &[])].concat();
+
+    let options = ParseOptions {
+        strictness: Strictness::Strict,
+        recover: true,
+        ..ParseOptions::default()
+    };
+    let output = parse_mp4_async_with(&mut Cursor::new(&input), &options)
+        .await
+        .unwrap();
+    assert_eq!(output.boxes.len(), 1);
+    assert_eq!(output.skipped.len(), 1);
+    assert_eq!(output.skipped[0].range, 0..120);
+
+    let output = parse_mp4_async_with(&mut Cursor::new(&input), &ParseOptions::default())
+        .await
+        .unwrap();
+    assert_eq!(output.boxes.len(), 2);
+    assert_eq!(output.warnings.len(), 1);
+
+    // A bad header is scanned past to the next box
+    let input = [vec![0xff; 8], boxed(b"free", &[])].concat();
+    let output = parse_mp4_async_with(&mut Cursor::new(&input), &options)
+        .await
+        .unwrap();
+    assert_eq!(output.boxes.len(), 1);
+    assert_eq!(output.skipped.len(), 1);
+    assert_eq!(output.skipped[0].range, 0..8);
+}
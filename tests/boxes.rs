@@ -0,0 +1,154 @@
+mod common;
+
+use bmf_parse::{boxes::*, *};
+use common::*;
+
+// The `$variant` boxes directly inside the first `$parent` box
+macro_rules! child_boxes {
+    ($boxes:expr, $parent:expr, $variant:ident) => {
+        find_box($boxes, $parent)
+            .unwrap()
+            .children()
+            .unwrap()
+            .iter()
+            .filter_map(|box_| match box_ {
+                Mp4Box::$variant(box_) => Some(box_.as_ref()),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+    };
+}
+
+#[test]
+fn elst_empty_then_media_edit() {
+    let boxes = parse_mp4(&sample_file()).unwrap();
+    let elst = child_boxes!(&boxes, b"edts", Elst)[0];
+
+    assert!(elst.entries[0].is_empty_edit());
+    assert!(!elst.entries[1].is_empty_edit());
+    assert_eq!(elst.presentation_delay(), 1000);
+    assert_eq!(elst.media_start(), Some(2048));
+    assert_eq!(elst.entries[1].media_rate_integer(), 1);
+    assert_eq!(elst.entries[1].media_rate_fraction(), 0);
+}
+
+#[test]
+fn elst_64_bit_entry() {
+    let entry = [
+        &[0, 0, 0, 1][..],
+        &(5u64 << 32).to_be_bytes(),
+        &(3i64 << 32).to_be_bytes(),
+        // Reverse playback at rate -1
+        &[0xff, 0xff, 0, 0],
+    ]
+    .concat();
+    let input = full(b"elst", 1, 0, &entry);
+    let boxes = parse_mp4(&input).unwrap();
+    let elst = match &boxes[0] {
+        Mp4Box::Elst(elst) => elst,
+        box_ => panic!("expected elst, found {:?}", box_),
+    };
+
+    assert_eq!(
+        elst.entries,
+        [ElstEntry {
+            segment_duration: 5 << 32,
+            media_time: 3 << 32,
+            media_rate: -0x10000,
+        }]
+    );
+    assert_eq!(elst.presentation_delay(), 0);
+    assert_eq!(elst.media_start(), Some(3 << 32));
+    assert_eq!(elst.entries[0].media_rate_integer(), -1);
+    assert_eq!(elst.entries[0].media_rate_fraction(), 0);
+    assert!(write_mp4(&boxes).unwrap() == input);
+}
+
+#[test]
+fn elst_raised_to_version_1() {
+    let input = full(
+        b"elst",
+        0,
+        0,
+        &[0, 0, 0, 1, 0, 0, 0x03, 0xe8, 0, 0, 0, 0, 0, 1, 0, 0],
+    );
+    let mut boxes = parse_mp4(&input).unwrap();
+    let elst = match &mut boxes[0] {
+        Mp4Box::Elst(elst) => elst,
+        box_ => panic!("expected elst, found {:?}", box_),
+    };
+    let entry = ElstEntry {
+        segment_duration: 1000,
+        media_time: 1 << 32,
+        media_rate: 0x10000,
+    };
+    elst.entries.push(entry);
+
+    let output = write_mp4(&boxes).unwrap();
+    assert_eq!(output[8], 1);
+    let boxes = parse_mp4(&output).unwrap();
+    let elst = match &boxes[0] {
+        Mp4Box::Elst(elst) => elst,
+        box_ => panic!("expected elst, found {:?}", box_),
+    };
+    assert_eq!(elst.entries[1], entry);
+    assert_eq!(elst.media_start(), Some(0));
+}
+
+#[test]
+fn sdtp_dependencies() {
+    let boxes = parse_mp4(&sample_file()).unwrap();
+    let sdtp = child_boxes!(&boxes, b"stbl", Sdtp)[0];
+
+    let dependencies: Vec<_> = sdtp.dependencies().collect();
+    assert_eq!(
+        dependencies,
+        [
+            SampleDependency {
+                is_leading: 0,
+                sample_depends_on: 2,
+                sample_is_depended_on: 1,
+                sample_has_redundancy: 0,
+            },
+            SampleDependency {
+                is_leading: 0,
+                sample_depends_on: 1,
+                sample_is_depended_on: 2,
+                sample_has_redundancy: 0,
+            },
+        ]
+    );
+    assert_eq!(dependencies[0].to_byte(), 0x24);
+}
+
+#[test]
+fn padb_high_nibble_first() {
+    let boxes = parse_mp4(&sample_file()).unwrap();
+    let padb = child_boxes!(&boxes, b"stbl", Padb)[0];
+
+    assert_eq!(padb.sample_count, 3);
+    assert_eq!(padb.pad(0), Some(1));
+    assert_eq!(padb.pad(1), Some(2));
+    assert_eq!(padb.pad(2), Some(3));
+    assert_eq!(padb.pad(4), None);
+}
+
+#[test]
+fn stz2_odd_4_bit_entries() {
+    let boxes = parse_mp4(&sample_file()).unwrap();
+    let stz2 = child_boxes!(&boxes, b"stbl", Stz2);
+    assert_eq!(stz2[0].entry_size, [1, 2, 3]);
+    assert_eq!(stz2[0]._padding, 0);
+    assert_eq!(stz2[1].entry_size, [0x100]);
+
+    // A non-zero low nibble after the last entry is kept for writing
+    let input = full(b"stz2", 0, 0, &[0, 0, 0, 4, 0, 0, 0, 3, 0xf1, 0x2a]);
+    let boxes = parse_mp4(&input).unwrap();
+    let stz2 = match &boxes[0] {
+        Mp4Box::Stz2(stz2) => stz2,
+        box_ => panic!("expected stz2, found {:?}", box_),
+    };
+    assert_eq!(stz2.entry_size, [0xf, 1, 2]);
+    assert_eq!(stz2._padding, 0xa);
+    assert!(write_mp4(&boxes).unwrap() == input);
+}
@@ -0,0 +1,136 @@
+mod common;
+
+use bmf_parse::{boxes::*, *};
+use common::*;
+
+fn find_senc(boxes: &[Mp4Box]) -> &BoxSenc {
+    match find_box(boxes, b"senc") {
+        Some(Mp4Box::Senc(senc)) => senc,
+        _ => panic!("no senc"),
+    }
+}
+
+#[test]
+fn senc_iv_sizes() {
+    // Two samples with 16-byte IVs and two subsamples each
+    let mut samples = vec![0, 0, 0, 2];
+    for sample in 0..2 {
+        samples.extend(filler(16));
+        samples.extend([0, 2, 0, 16, 0, 0, 1, 0, 0, sample, 0, 0, 0, 32]);
+    }
+    let input = full(b"senc", 0, 2, &samples);
+    let boxes = parse_mp4(&input).unwrap();
+    assert!(write_mp4(&boxes).unwrap() == input);
+
+    let senc = find_senc(&boxes);
+    assert!(senc.samples(8).is_none());
+    let samples = senc.samples(16).unwrap();
+    assert_eq!(samples[1].iv, filler(16));
+    assert_eq!(
+        samples[1].subsamples.as_deref(),
+        Some(
+            &[
+                Subsample {
+                    clear_bytes: 16,
+                    cipher_bytes: 256,
+                },
+                Subsample {
+                    clear_bytes: 1,
+                    cipher_bytes: 32,
+                },
+            ][..]
+        )
+    );
+
+    // cbcs with a constant IV, only the subsample maps are stored
+    let input = full(b"senc", 0, 2, &[0, 0, 0, 1, 0, 1, 0, 0, 0, 0, 0, 16]);
+    let boxes = parse_mp4(&input).unwrap();
+    let samples = find_senc(&boxes).samples(0).unwrap();
+    assert!(samples[0].iv.is_empty());
+    assert_eq!(samples[0].subsamples.as_ref().unwrap().len(), 1);
+}
+
+#[test]
+fn piff_senc_iv_size_override() {
+    let usertype = [
+        0xa2, 0x39, 0x4f, 0x52, 0x5a, 0x9b, 0x4f, 0x14, 0xa2, 0x44, 0x6c, 0x42, 0x7c, 0x64, 0x8d,
+        0xf4,
+    ];
+    let payload = [
+        &usertype[..],
+        &[0, 0, 0, 1],
+        &[0, 0, 1, 16],
+        &filler(16),
+        &[0, 0, 0, 1],
+        &filler(16),
+    ]
+    .concat();
+    let input = boxed(b"uuid", &payload);
+    let boxes = parse_mp4(&input).unwrap();
+    assert!(write_mp4(&boxes).unwrap() == input);
+
+    let senc = match &boxes[0] {
+        Mp4Box::Uuid(uuid) => match &uuid.data {
+            UuidData::PiffSenc(senc) => senc,
+            data => panic!("not a PIFF senc: {:?}", data),
+        },
+        _ => panic!("not a uuid box"),
+    };
+    assert_eq!(senc.iv_size, Some(16));
+    // The tenc default of 8 is overridden
+    assert_eq!(senc.samples(8).unwrap()[0].iv, filler(16));
+}
+
+#[test]
+fn pssh_payloads() {
+    let boxes = parse_mp4(&common::sample_file()).unwrap();
+    let kid: [u8; 16] = core::array::from_fn(|i| 0x10 + i as u8);
+
+    let pssh: Vec<&BoxPssh> = find_box(&boxes, b"moov")
+        .unwrap()
+        .children()
+        .unwrap()
+        .iter()
+        .filter_map(|box_| match box_ {
+            Mp4Box::Pssh(pssh) => Some(pssh.as_ref()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(pssh.len(), 2);
+
+    let widevine = pssh[0].widevine().unwrap();
+    assert!(pssh[0].playready().is_none());
+    assert_eq!(widevine.algorithm, Some(1));
+    assert_eq!(widevine.key_ids, [kid]);
+    assert_eq!(widevine.provider.as_deref(), Some("test"));
+    assert_eq!(widevine.content_id, Some(vec![0xab, 0xcd]));
+    assert_eq!(widevine.protection_scheme, Some(*b"cenc"));
+
+    assert_eq!(pssh[1].kids, [kid]);
+    assert!(pssh[1].widevine().is_none());
+    let header = pssh[1].playready().unwrap().wrm_header().unwrap();
+    assert_eq!(header.version, "4.3.0.0");
+    assert_eq!(header.kids, [kid]);
+    assert_eq!(header.algorithm.as_deref(), Some("AESCBC"));
+    assert_eq!(
+        header.la_url.as_deref(),
+        Some("https://example.com/rightsmanager.asmx?a=1&b=2")
+    );
+
+    // Version 4.0 header in the PIFF pssh
+    let piff = boxes
+        .iter()
+        .find_map(|box_| match box_ {
+            Mp4Box::Uuid(uuid) => match &uuid.data {
+                UuidData::PiffPssh(pssh) => Some(pssh),
+                _ => None,
+            },
+            _ => None,
+        })
+        .unwrap();
+    let header = piff.playready().unwrap().wrm_header().unwrap();
+    assert_eq!(header.version, "4.0.0.0");
+    assert_eq!(header.kids, [kid]);
+    assert_eq!(header.algorithm.as_deref(), Some("AESCTR"));
+    assert_eq!(header.la_url, None);
+}
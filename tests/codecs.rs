@@ -0,0 +1,243 @@
+mod common;
+
+use bmf_parse::{boxes::*, *};
+
+fn entry<'a>(boxes: &'a [Mp4Box], format: &[u8; 4]) -> &'a SampleEntry {
+    let stsd = match find_box(boxes, b"stsd") {
+        Some(Mp4Box::Stsd(stsd)) => stsd,
+        _ => panic!("no stsd"),
+    };
+    stsd.entries
+        .iter()
+        .find(|entry| &entry.format() == format)
+        .unwrap()
+}
+
+// The `$variant` box directly inside the `$format` sample entry
+macro_rules! entry_box {
+    ($boxes:expr, $format:expr, $variant:ident) => {
+        entry($boxes, $format)
+            .children()
+            .unwrap()
+            .iter()
+            .find_map(|box_| match box_ {
+                Mp4Box::$variant(box_) => Some(box_.as_ref()),
+                _ => None,
+            })
+            .unwrap()
+    };
+}
+
+#[test]
+fn avcc() {
+    let boxes = parse_mp4(&common::sample_file()).unwrap();
+    let avcc = entry_box!(&boxes, b"avc1", Avcc);
+
+    assert_eq!(avcc.profile_indication, 100);
+    assert_eq!(avcc.level_indication, 40);
+    assert_eq!(avcc.nal_length_size(), 4);
+    assert_eq!(avcc.sps, [vec![0x67, 0x64, 0, 0x28]]);
+    assert_eq!(avcc.pps, [vec![0x68, 0xee]]);
+
+    let ext = avcc.ext.as_ref().unwrap();
+    assert_eq!(ext.chroma_format, 1);
+    assert_eq!(ext.bit_depth_luma_minus8, 0);
+    assert!(ext.sps_ext.is_empty());
+}
+
+#[test]
+fn hvcc() {
+    let boxes = parse_mp4(&common::sample_file()).unwrap();
+    let hvcc = entry_box!(&boxes, b"hvc1", Hvcc);
+
+    assert_eq!(hvcc.general_profile_idc, 1);
+    assert!(hvcc.general_tier_flag);
+    assert_eq!(hvcc.general_profile_compatibility_flags, 0x6000_0000);
+    assert_eq!(hvcc.general_constraint_indicator_flags, 0x9000_0000_0000);
+    assert_eq!(hvcc.general_level_idc, 93);
+    assert_eq!(hvcc.chroma_format_idc, 1);
+    assert_eq!(hvcc.num_temporal_layers, 1);
+    assert!(hvcc.temporal_id_nested);
+    assert_eq!(hvcc.nal_length_size(), 4);
+
+    let types: Vec<_> = hvcc
+        .arrays
+        .iter()
+        .map(|array| array.nal_unit_type)
+        .collect();
+    assert_eq!(types, [32, 33, 34]);
+    assert!(hvcc.arrays[0].array_completeness);
+    assert!(!hvcc.arrays[2].array_completeness);
+    let sps: Vec<_> = hvcc.nal_units(33).collect();
+    assert_eq!(sps, [&vec![0x42, 1, 1]]);
+}
+
+#[test]
+fn av1c() {
+    let boxes = parse_mp4(&common::sample_file()).unwrap();
+    let av1c = entry_box!(&boxes, b"av01", Av1c);
+
+    assert!(av1c.marker);
+    assert_eq!(av1c.version, 1);
+    assert_eq!(av1c.seq_profile, 0);
+    assert_eq!(av1c.seq_level_idx_0, 8);
+    assert!(!av1c.seq_tier_0);
+    assert_eq!(av1c.bit_depth(), 8);
+    assert!(av1c.chroma_subsampling_x && av1c.chroma_subsampling_y);
+    assert_eq!(av1c.initial_presentation_delay_minus_one, None);
+    assert_eq!(av1c.config_obus, [0x0a, 0x0b, 0, 0, 0, 0x42]);
+}
+
+#[test]
+fn vpcc() {
+    let boxes = parse_mp4(&common::sample_file()).unwrap();
+    let vpcc = entry_box!(&boxes, b"vp09", Vpcc);
+
+    assert_eq!(vpcc.profile, 0);
+    assert_eq!(vpcc.level, 10);
+    assert_eq!(vpcc.bit_depth, 8);
+    assert_eq!(vpcc.chroma_subsampling, 1);
+    assert!(!vpcc.video_full_range_flag);
+    assert_eq!(
+        (
+            vpcc.colour_primaries,
+            vpcc.transfer_characteristics,
+            vpcc.matrix_coefficients
+        ),
+        (1, 1, 1)
+    );
+    assert!(vpcc.codec_initialization_data.is_empty());
+}
+
+#[test]
+fn esds() {
+    let boxes = parse_mp4(&common::sample_file()).unwrap();
+    let esds = entry_box!(&boxes, b"mp4a", Esds);
+
+    assert_eq!(esds.descriptor.size_length, 4);
+    assert_eq!(esds.es_descriptor().unwrap().es_id, 1);
+
+    let config = esds.decoder_config().unwrap();
+    assert_eq!(config.object_type_indication, 0x40);
+    assert_eq!(config.stream_type, 5);
+    assert_eq!(config.max_bitrate, 128000);
+    assert_eq!(config.avg_bitrate, 128000);
+
+    // HE-AAC v2, signalled after the AAC LC core config
+    let asc = esds.audio_specific_config().unwrap();
+    assert_eq!(asc.audio_object_type, 2);
+    assert_eq!(asc.channel_configuration, 2);
+    assert!(asc.sbr_present);
+    assert!(asc.ps_present);
+    assert_eq!(asc.sample_rate(), Some(24000));
+    assert_eq!(asc.output_sample_rate(), Some(48000));
+}
+
+#[test]
+fn dops_and_chnl() {
+    let boxes = parse_mp4(&common::sample_file()).unwrap();
+    let dops = entry_box!(&boxes, b"Opus", Dops);
+
+    assert_eq!(dops.output_channel_count, 2);
+    assert_eq!(dops.pre_skip, 312);
+    assert_eq!(dops.input_sample_rate, 48000);
+    assert_eq!(dops.channel_mapping_family, 1);
+    let table = dops.channel_mapping_table.as_ref().unwrap();
+    assert_eq!((table.stream_count, table.coupled_count), (1, 1));
+    assert_eq!(table.channel_mapping, [0, 1]);
+
+    let chnl = entry_box!(&boxes, b"Opus", Chnl);
+    assert_eq!(chnl.stream_structure, BoxChnl::CHANNEL_STRUCTURED);
+    assert_eq!(chnl.defined_layout, Some(0));
+    assert_eq!(chnl.speaker_positions.len(), 2);
+    assert_eq!(chnl.speaker_positions[0].speaker_position, 2);
+    let explicit = &chnl.speaker_positions[1];
+    assert_eq!(explicit.speaker_position, SpeakerPosition::EXPLICIT);
+    assert_eq!((explicit.azimuth, explicit.elevation), (30, -5));
+}
+
+#[test]
+fn dfla() {
+    let boxes = parse_mp4(&common::sample_file()).unwrap();
+    let dfla = entry_box!(&boxes, b"fLaC", Dfla);
+
+    assert_eq!(dfla.blocks.len(), 1);
+    assert!(dfla.blocks[0].last_metadata_block_flag);
+    let info = dfla.stream_info().unwrap();
+    assert_eq!((info.min_block_size, info.max_block_size), (4096, 4096));
+    assert_eq!((info.min_frame_size, info.max_frame_size), (16, 16384));
+    assert_eq!(info.sample_rate, 48000);
+    assert_eq!(info.channels_minus_one, 1);
+    assert_eq!(info.bits_per_sample_minus_one, 15);
+    assert_eq!(info.total_samples, 0x12345);
+}
+
+#[test]
+fn dac3_dec3_dac4() {
+    let boxes = parse_mp4(&common::sample_file()).unwrap();
+
+    let dac3 = entry_box!(&boxes, b"ac-3", Dac3);
+    assert_eq!((dac3.fscod, dac3.bsid, dac3.bsmod), (0, 8, 0));
+    assert_eq!(dac3.acmod, 7);
+    assert!(dac3.lfeon);
+    assert_eq!(dac3.bit_rate_code, 14);
+
+    let dec3 = entry_box!(&boxes, b"ec-3", Dec3);
+    assert_eq!(dec3.data_rate, 448);
+    assert_eq!(dec3.substreams.len(), 1);
+    let substream = &dec3.substreams[0];
+    assert_eq!(
+        (substream.fscod, substream.bsid, substream.acmod),
+        (0, 16, 7)
+    );
+    assert!(substream.lfeon);
+    assert_eq!(substream.num_dep_sub, 1);
+    assert_eq!(substream.chan_loc, 0x102);
+    assert_eq!(dec3.extension, [1, 0x10]);
+
+    let dac4 = entry_box!(&boxes, b"ac-4", Dac4);
+    assert_eq!(dac4.ac4_dsi_version, 1);
+    assert_eq!(dac4.bitstream_version, 2);
+    assert_eq!(dac4.sample_rate(), 48000);
+    assert_eq!(dac4.frame_rate_index, 2);
+    assert_eq!(dac4.n_presentations, 1);
+
+    // Version 1 layout
+    let chnl = entry_box!(&boxes, b"ac-4", Chnl);
+    assert_eq!(chnl.stream_structure, BoxChnl::CHANNEL_STRUCTURED);
+    assert_eq!(chnl.base_channel_count, 6);
+    assert_eq!(chnl.defined_layout, Some(12));
+    assert_eq!(chnl.channel_order_definition, 1);
+    assert_eq!(chnl.omitted_channels_map, Some(0x30));
+}
+
+#[test]
+fn avcc_high_444_extension() {
+    let avcc = [
+        &[
+            1, 244, 0, 50, 0xff, 0xe1, 0, 2, 0x67, 0xf4, 1, 0, 2, 0x68, 0xce,
+        ][..],
+        // 4:4:4, 10-bit luma and chroma, one SPS extension
+        &[0xff, 0xfa, 0xfa, 1, 0, 2, 0x6d, 0x01],
+    ]
+    .concat();
+    let input = common::boxed(b"avcC", &avcc);
+    let options = ParseOptions {
+        strictness: Strictness::Strict,
+        ..ParseOptions::default()
+    };
+    let boxes = parse_mp4_with(&input, &options).unwrap().boxes;
+    assert!(write_mp4(&boxes).unwrap() == input);
+
+    let avcc = match &boxes[0] {
+        Mp4Box::Avcc(avcc) => avcc,
+        box_ => panic!("expected avcC, found {:?}", box_),
+    };
+    assert_eq!(avcc.profile_indication, 244);
+    let ext = avcc.ext.as_ref().unwrap();
+    assert_eq!(ext.chroma_format, 3);
+    assert_eq!(ext.bit_depth_luma_minus8, 2);
+    assert_eq!(ext.bit_depth_chroma_minus8, 2);
+    assert_eq!(ext.sps_ext, [vec![0x6d, 0x01]]);
+    assert!(avcc.layout.trailing.is_empty());
+}
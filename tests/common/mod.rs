@@ -0,0 +1,418 @@
+// Box builders and the sample file shared by the integration tests
+#![allow(dead_code)]
+
+use bmf_parse::*;
+
+pub fn boxed(box_type: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+    let mut output = ((payload.len() + 8) as u32).to_be_bytes().to_vec();
+    output.extend_from_slice(box_type);
+    output.extend_from_slice(payload);
+    output
+}
+
+pub fn full(box_type: &[u8; 4], version: u8, flags: u32, payload: &[u8]) -> Vec<u8> {
+    let mut data = flags.to_be_bytes().to_vec();
+    data[0] = version;
+    data.extend_from_slice(payload);
+    boxed(box_type, &data)
+}
+
+// Arbitrary bytes for fixed-size fields
+pub fn filler(len: usize) -> Vec<u8> {
+    (0..len).map(|i| i as u8).collect()
+}
+
+pub fn pssh(version: u8, system_id: [u8; 16], kids: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut payload = system_id.to_vec();
+    if version > 0 {
+        payload.extend(((kids.len() / 16) as u32).to_be_bytes());
+        payload.extend_from_slice(kids);
+    }
+    payload.extend((data.len() as u32).to_be_bytes());
+    payload.extend_from_slice(data);
+    full(b"pssh", version, 0, &payload)
+}
+
+// PlayReady Object with a single rights management header
+pub fn playready_object(xml: &str) -> Vec<u8> {
+    let xml: Vec<u8> = xml.encode_utf16().flat_map(u16::to_le_bytes).collect();
+    let mut output = ((xml.len() + 10) as u32).to_le_bytes().to_vec();
+    output.extend(1u16.to_le_bytes());
+    output.extend(1u16.to_le_bytes());
+    output.extend((xml.len() as u16).to_le_bytes());
+    output.extend(xml);
+    output
+}
+
+// Every box type the parser models, with the layout details it has to keep
+pub fn sample_file() -> Vec<u8> {
+    let piff_senc = [
+        0xa2, 0x39, 0x4f, 0x52, 0x5a, 0x9b, 0x4f, 0x14, 0xa2, 0x44, 0x6c, 0x42, 0x7c, 0x64, 0x8d,
+        0xf4,
+    ];
+    let piff_pssh = [
+        0xd0, 0x8a, 0x4f, 0x18, 0x10, 0xf3, 0x4a, 0x82, 0xb6, 0xc8, 0x32, 0xd8, 0xab, 0xa1, 0x83,
+        0xd3,
+    ];
+
+    let kid: Vec<u8> = (0x10..0x20).collect();
+    let widevine = [
+        &[0x08, 0x01, 0x12, 0x10][..],
+        &kid,
+        &[0x1a, 0x04],
+        b"test",
+        &[0x22, 0x02, 0xab, 0xcd],
+        &[0x48, 0xe3, 0xdc, 0x95, 0x9b, 0x06], // cenc
+    ]
+    .concat();
+    let playready = playready_object(concat!(
+        r#"<WRMHEADER xmlns="http://schemas.microsoft.com/DRM/2007/03/PlayReadyHeader" version="4.3.0.0">"#,
+        r#"<DATA><PROTECTINFO><KIDS><KID ALGID="AESCBC" VALUE="ExIREBUUFxYYGRobHB0eHw=="></KID></KIDS></PROTECTINFO>"#,
+        r#"<LA_URL>https://example.com/rightsmanager.asmx?a=1&amp;b=2</LA_URL></DATA></WRMHEADER>"#,
+    ));
+    let playready_v4 = playready_object(concat!(
+        r#"<WRMHEADER xmlns="http://schemas.microsoft.com/DRM/2007/03/PlayReadyHeader" version="4.0.0.0">"#,
+        r#"<DATA><PROTECTINFO><KEYLEN>16</KEYLEN><ALGID>AESCTR</ALGID></PROTECTINFO>"#,
+        r#"<KID>ExIREBUUFxYYGRobHB0eHw==</KID></DATA></WRMHEADER>"#,
+    ));
+
+    let mut file = boxed(b"ftyp", b"isom\0\0\x02\0isomiso2\0\0"); // 2 bytes of padding
+
+    let visual = [
+        &[0, 0, 0, 0, 0, 0, 0, 1][..],
+        &[0; 16],
+        &[
+            0x07, 0x80, 0x04, 0x38, 0, 0x48, 0, 0, 0, 0x48, 0, 0, 0, 0, 0, 0, 0, 1,
+        ],
+        &[&[4][..], b"test", &[0; 27]].concat(),
+        &[0, 0x18, 0xff, 0xff],
+    ]
+    .concat();
+    let avc1 = [
+        &visual[..],
+        &boxed(
+            b"avcC",
+            &[
+                1, 100, 0, 40, 0xff, 0xe1, 0, 4, 0x67, 0x64, 0, 0x28, 1, 0, 2, 0x68, 0xee, 0xfd,
+                0xf8, 0xf8, 0,
+            ],
+        ),
+        &boxed(b"pasp", &[0, 0, 0, 1, 0, 0, 0, 1]),
+    ]
+    .concat();
+    let hvc1 = [
+        &visual[..],
+        &boxed(
+            b"hvcC",
+            &[
+                // Main profile, high tier
+                1, 0x21, 0x60, 0, 0, 0, 0x90, 0, 0, 0, 0, 0, 0x5d, 0xf0, 0, 0xfc, 0xfd, 0xf8, 0xf8,
+                0, 0, 0x0f, 3, 0xa0, 0, 1, 0, 4, 0x40, 1, 0x0c, 1, 0xa1, 0, 1, 0, 3, 0x42, 1, 1,
+                0x22, 0, 1, 0, 2, 0x44, 1,
+            ],
+        ),
+    ]
+    .concat();
+    let av01 = [
+        &visual[..],
+        &boxed(
+            b"av1C",
+            &[0x81, 0x08, 0x0c, 0x00, 0x0a, 0x0b, 0, 0, 0, 0x42],
+        ),
+    ]
+    .concat();
+    let vp09 = [
+        &visual[..],
+        &full(b"vpcC", 1, 0, &[0, 10, 0x82, 1, 1, 1, 0, 0]),
+    ]
+    .concat();
+    let audio = [
+        &[0, 0, 0, 0, 0, 0, 0, 1][..],
+        &[0; 8],
+        &[0, 2, 0, 16, 0, 0, 0, 0, 0xbb, 0x80, 0, 0],
+    ]
+    .concat();
+    let mp4a = [
+        &audio[..],
+        &full(
+            b"esds",
+            0,
+            0,
+            &[
+                // ES_Descriptor with padded sizes, HE-AAC v2 signalled after the core config
+                3, 0x80, 0x80, 0x80, 36, 0, 1, 0, 4, 0x80, 0x80, 0x80, 25, 0x40, 0x15, 0, 6, 0, 0,
+                1, 0xf4, 0, 0, 1, 0xf4, 0, 5, 0x80, 0x80, 0x80, 7, 0x13, 0x10, 0x56, 0xe5, 0x9d,
+                0x48, 0x80, 6, 1, 2,
+            ],
+        ),
+    ]
+    .concat();
+    let opus = [
+        &audio[..],
+        &boxed(
+            b"dOps",
+            &[0, 2, 1, 0x38, 0, 0, 0xbb, 0x80, 0, 0, 1, 1, 1, 0, 1],
+        ),
+        // Version 0 layout with an explicit position
+        &full(b"chnl", 0, 0, &[1, 0, 2, 126, 0, 30, 0xfb]),
+    ]
+    .concat();
+    let flac = [
+        &audio[..],
+        &full(
+            b"dfLa",
+            0,
+            0,
+            &[
+                &[0x80, 0, 0, 34, 0x10, 0, 0x10, 0, 0, 0, 16, 0, 0x40, 0][..],
+                &[0x0b, 0xb8, 0x02, 0xf0, 0, 0x01, 0x23, 0x45],
+                &filler(16),
+            ]
+            .concat(),
+        ),
+    ]
+    .concat();
+    let ac3 = [&audio[..], &boxed(b"dac3", &[0x10, 0x3d, 0xc0])].concat();
+    let ec3 = [
+        &audio[..],
+        &boxed(b"dec3", &[0x0e, 0, 0x20, 0x0f, 0x03, 0x02, 0x01, 0x10]),
+    ]
+    .concat();
+    let ac4 = [
+        &audio[..],
+        &boxed(b"dac4", &[0x20, 0xa4, 0x01, 0x80, 0, 0, 0]),
+        // Version 1 layout with omitted channels
+        &full(
+            b"chnl",
+            1,
+            0,
+            &[0x10, 6, 12, 0x03, 0, 0, 0, 0, 0, 0, 0, 0x30],
+        ),
+    ]
+    .concat();
+    // cbcs with a constant IV and pattern encryption
+    let encv = [
+        &visual[..],
+        &boxed(
+            b"sinf",
+            &[
+                boxed(b"frma", b"avc1"),
+                full(b"schm", 0, 0, &[&b"cbcs"[..], &[0, 1, 0, 0]].concat()),
+                boxed(
+                    b"schi",
+                    &full(
+                        b"tenc",
+                        1,
+                        0,
+                        &[&[0, 0x19, 1, 0][..], &kid, &[16], &filler(16)].concat(),
+                    ),
+                ),
+            ]
+            .concat(),
+        ),
+    ]
+    .concat();
+    // cenc with per-sample IVs and a scheme URI
+    let enca = [
+        &audio[..],
+        &boxed(
+            b"sinf",
+            &[
+                boxed(b"frma", b"mp4a"),
+                full(
+                    b"schm",
+                    0,
+                    1,
+                    &[&b"cenc"[..], &[0, 1, 0, 0], b"urn:test\0"].concat(),
+                ),
+                boxed(
+                    b"schi",
+                    &full(b"tenc", 0, 0, &[&[0, 0, 1, 8][..], &kid].concat()),
+                ),
+            ]
+            .concat(),
+        ),
+    ]
+    .concat();
+    let entries = [
+        &[0, 0, 0, 14][..],
+        &boxed(b"avc1", &avc1),
+        &boxed(b"hvc1", &hvc1),
+        &boxed(b"av01", &av01),
+        &boxed(b"vp09", &vp09),
+        &boxed(b"mp4a", &mp4a),
+        &boxed(b"Opus", &opus),
+        &boxed(b"fLaC", &flac),
+        &boxed(b"ac-3", &ac3),
+        &boxed(b"ec-3", &ec3),
+        &boxed(b"ac-4", &ac4),
+        &boxed(b"encv", &encv),
+        &boxed(b"enca", &enca),
+        &boxed(
+            b"tx3g",
+            &[&[0, 0, 0, 0, 0, 0, 0, 1][..], &filler(10)].concat(),
+        ),
+        &boxed(b"abcd", &[1, 2]),
+    ]
+    .concat();
+
+    let stbl = [
+        full(b"stsd", 0, 0, &entries),
+        full(
+            b"stts",
+            0,
+            0,
+            &[0, 0, 0, 2, 0, 0, 0, 1, 0, 0, 4, 0, 0, 0, 0, 1, 0, 0, 2, 0],
+        ),
+        full(
+            b"stsc",
+            0,
+            0,
+            &[0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 1],
+        ),
+        full(
+            b"stsz",
+            0,
+            0,
+            &[0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 1, 0, 0, 0, 2, 0],
+        ),
+        full(b"stsz", 0, 0, &[0, 0, 1, 0, 0, 0, 0, 2]),
+        full(b"stco", 0, 0, &[0, 0, 0, 1, 0, 0, 0, 64]),
+        full(b"co64", 0, 0, &[0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0, 0]),
+        // Odd number of 4-bit sizes, the last nibble is padding
+        full(b"stz2", 0, 0, &[0, 0, 0, 4, 0, 0, 0, 3, 0x12, 0x30]),
+        full(b"stz2", 0, 0, &[0, 0, 0, 16, 0, 0, 0, 1, 1, 0]),
+        full(b"stss", 0, 0, &[0, 0, 0, 1, 0, 0, 0, 1]),
+        full(
+            b"ctts",
+            1,
+            0,
+            &[
+                0, 0, 0, 2, 0, 0, 0, 1, 0xff, 0xff, 0xfc, 0, 0, 0, 0, 1, 0, 0, 4, 0,
+            ],
+        ),
+        full(b"cslg", 0, 0, &filler(20)),
+        full(b"cslg", 1, 0, &filler(40)),
+        full(b"stsh", 0, 0, &[0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 1]),
+        full(b"sdtp", 0, 0, &[0x24, 0x18]),
+        full(b"padb", 0, 0, &[0, 0, 0, 3, 0x12, 0x30]),
+    ]
+    .concat();
+    let minf = [
+        full(b"smhd", 0, 0, &[0, 0, 0, 0]),
+        boxed(b"dinf", &full(b"dref", 0, 0, &filler(16))),
+        boxed(b"stbl", &stbl),
+    ]
+    .concat();
+    let mdia = [
+        full(b"mdhd", 0, 0, &filler(20)),
+        full(
+            b"hdlr",
+            0,
+            0,
+            &[&filler(20)[..], b"SoundHandler\0"].concat(),
+        ),
+        // QuickTime style name without a terminator
+        full(
+            b"hdlr",
+            0,
+            0,
+            &[&filler(20)[..], b"Core Media Audio"].concat(),
+        ),
+        boxed(b"minf", &minf),
+    ]
+    .concat();
+    // Empty edit then one starting after the priming samples, version 1 kept with small values
+    let edts = [
+        full(
+            b"elst",
+            0,
+            0,
+            &[
+                0, 0, 0, 2, 0, 0, 0x03, 0xe8, 0xff, 0xff, 0xff, 0xff, 0, 1, 0, 0, 0, 0, 0x27, 0x10,
+                0, 0, 0x08, 0, 0, 1, 0, 0,
+            ],
+        ),
+        full(
+            b"elst",
+            1,
+            0,
+            &[
+                &[0, 0, 0, 1][..],
+                &[0; 7],
+                &[0x10],
+                &[0; 7],
+                &[0x08],
+                &[0, 1, 0, 0],
+            ]
+            .concat(),
+        ),
+    ]
+    .concat();
+    let trak = [
+        full(b"tkhd", 1, 7, &filler(92)),
+        boxed(b"edts", &edts),
+        boxed(b"mdia", &mdia),
+    ]
+    .concat();
+    let moov = [
+        full(b"mvhd", 0, 0, &filler(96)),
+        boxed(b"trak", &trak),
+        boxed(b"mvex", &full(b"trex", 0, 0, &filler(20))),
+        pssh(0, boxes::BoxPssh::WIDEVINE, &[], &widevine),
+        pssh(1, boxes::BoxPssh::PLAYREADY, &kid, &playready),
+        boxed(b"udta", &filler(12)),
+        vec![0; 4], // Terminator some muxers write after the last child
+    ]
+    .concat();
+    file.extend(boxed(b"moov", &moov));
+
+    let senc = [&[0, 0, 0, 1][..], &filler(8), &[0, 1, 0, 4, 0, 0, 0, 16]].concat();
+    let trun = [
+        &[0, 0, 0, 2, 0, 0, 0, 8, 0, 0, 0, 0][..],
+        &filler(16),
+        &filler(16),
+    ]
+    .concat();
+    let traf = [
+        full(b"tfhd", 0, 0x3b, &filler(28)),
+        full(b"tfdt", 1, 0, &filler(8)),
+        full(b"senc", 0, 2, &senc),
+        full(b"saiz", 0, 0, &[0, 0, 0, 0, 3, 8, 8, 8]),
+        full(b"saio", 1, 0, &[0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 1, 0]),
+        full(b"trun", 1, 0xf05, &trun),
+        full(b"sgpd", 1, 0, &filler(12)),
+        full(b"sbgp", 0, 0, &filler(12)),
+    ]
+    .concat();
+    let moof = [full(b"mfhd", 0, 0, &[0, 0, 0, 1]), boxed(b"traf", &traf)].concat();
+    file.extend(boxed(b"moof", &moof));
+
+    let piff_senc = [
+        &piff_senc[..],
+        &full(b"senc", 0, 0, &[0, 0, 0, 1])[8..],
+        &filler(8),
+    ];
+    file.extend(boxed(b"uuid", &piff_senc.concat()));
+    let piff_pssh = [
+        &piff_pssh[..],
+        &pssh(0, boxes::BoxPssh::PLAYREADY, &[], &playready_v4)[8..],
+    ];
+    file.extend(boxed(b"uuid", &piff_pssh.concat()));
+    file.extend(boxed(b"uuid", &filler(20)));
+    file.extend(boxed(b"free", &filler(4)));
+    file.extend(boxed(b"abcd", &filler(6)));
+
+    // Small mdat with a largesize header
+    file.extend(1u32.to_be_bytes());
+    file.extend(b"mdat");
+    file.extend(24u64.to_be_bytes());
+    file.extend(filler(8));
+
+    // Final mdat runs to the end of the file
+    file.extend(0u32.to_be_bytes());
+    file.extend(b"mdat");
+    file.extend(filler(32));
+
+    file
+}
@@ -0,0 +1,66 @@
+mod common;
+
+use bmf_parse::*;
+use std::io::Cursor;
+
+fn tfdt_only(lazy_mdat: bool) -> ParseOptions {
+    ParseOptions {
+        lazy_mdat,
+        filter: Some(BoxFilter::paths(&["moof/traf/tfdt"])),
+        ..ParseOptions::default()
+    }
+}
+
+fn is_unknown(box_: &Mp4Box) -> bool {
+    matches!(box_, Mp4Box::Unknown { .. })
+}
+
+#[test]
+fn filter_decodes_selected_paths() {
+    let input = common::sample_file();
+    let output = parse_mp4_with(&input, &tfdt_only(false)).unwrap();
+    let boxes = &output.boxes;
+
+    assert!(is_unknown(find_box(boxes, b"moov").unwrap()));
+    assert!(matches!(find_box(boxes, b"moof"), Some(Mp4Box::Moof(_))));
+    assert!(matches!(find_box(boxes, b"traf"), Some(Mp4Box::Traf(_))));
+    assert!(matches!(find_box(boxes, b"tfdt"), Some(Mp4Box::Tfdt(_))));
+    assert!(is_unknown(find_box(boxes, b"mfhd").unwrap()));
+    assert!(is_unknown(find_box(boxes, b"trun").unwrap()));
+
+    assert!(write_mp4(boxes).unwrap() == input);
+}
+
+#[test]
+fn filtered_boxes_stay_in_input() {
+    let input = common::sample_file();
+    let mut boxes = parse_mp4_with(&input, &tfdt_only(true)).unwrap().boxes;
+
+    let moov = find_box(&boxes, b"moov").unwrap();
+    assert!(!moov.payload().unwrap().is_loaded());
+    assert!(write_mp4(&boxes).is_err());
+
+    load_payloads(&mut boxes, &mut Cursor::new(&input)).unwrap();
+    assert!(write_mp4(&boxes).unwrap() == input);
+}
+
+#[test]
+fn reader_skips_filtered_boxes() {
+    let input = common::sample_file();
+    let mut reader = Cursor::new(&input);
+    let mut boxes = parse_mp4_reader_with(&mut reader, &tfdt_only(false))
+        .unwrap()
+        .boxes;
+
+    assert!(!find_box(&boxes, b"moov")
+        .unwrap()
+        .payload()
+        .unwrap()
+        .is_loaded());
+    assert!(matches!(find_box(&boxes, b"tfdt"), Some(Mp4Box::Tfdt(_))));
+    // Filtered like any other box, even though it would keep a range anyway
+    assert!(is_unknown(find_box(&boxes, b"mdat").unwrap()));
+
+    load_payloads(&mut boxes, &mut reader).unwrap();
+    assert!(write_mp4(&boxes).unwrap() == input);
+}
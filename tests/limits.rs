@@ -0,0 +1,148 @@
+mod common;
+
+use bmf_parse::*;
+use common::*;
+use std::io::Cursor;
+
+// ES_Descriptors nested `depth` deep, with 4-byte sizes
+fn nested_descriptors(depth: usize) -> Vec<u8> {
+    let mut descriptors = vec![];
+    for level in (0..depth).rev() {
+        // Each level holds its own 3 bytes of fields and 8 per descriptor inside it
+        let size = level as u32 * 8 + 3;
+        descriptors.push(3);
+        for i in (0..4).rev() {
+            let more = if i > 0 { 0x80 } else { 0 };
+            descriptors.push(more | (size >> (7 * i)) as u8 & 0x7F);
+        }
+        descriptors.extend([0, 1, 0]);
+    }
+    descriptors
+}
+
+#[test]
+fn nested_descriptors_too_deep() {
+    let input = full(b"esds", 0, 0, &nested_descriptors(200_000));
+    let err = parse_mp4(&input).unwrap_err();
+    assert_eq!(err.kind, ParseErrorKind::TooDeep(65));
+    assert_eq!(err.path, "esds");
+
+    let input = full(b"esds", 0, 0, &nested_descriptors(63));
+    assert!(write_mp4(&parse_mp4(&input).unwrap()).unwrap() == input);
+}
+
+#[test]
+fn hostile_entry_count() {
+    // stts declaring 4 billion entries with room for one
+    let input = full(
+        b"stts",
+        0,
+        0,
+        &[0xff, 0xff, 0xff, 0xff, 0, 0, 0, 1, 0, 0, 0, 1],
+    );
+    let err = parse_mp4(&input).unwrap_err();
+    assert_eq!(err.kind, ParseErrorKind::CountOverflow(u32::MAX as u64));
+    assert_eq!(err.field, Some("entries"));
+    assert_eq!(err.offset, 16);
+}
+
+#[test]
+fn entry_tables_over_alloc_limit() {
+    let mut entries = vec![0, 0, 0, 64];
+    entries.extend(filler(64 * 8));
+    let input = full(b"stts", 0, 0, &entries);
+
+    let mut options = ParseOptions::default();
+    options.limits.max_alloc = 1024;
+    let err = parse_mp4_with(&input, &options).unwrap_err();
+    assert!(matches!(err.kind, ParseErrorKind::AllocLimit(_)));
+    assert_eq!(err.path, "stts");
+
+    options.limits.max_alloc = 1 << 20;
+    assert!(parse_mp4_with(&input, &options).is_ok());
+}
+
+#[test]
+fn sized_tables_over_alloc_limit() {
+    let mut options = ParseOptions::default();
+    options.limits.max_alloc = 1024;
+
+    // 1024 4-bit sizes, unpacked to u16
+    let mut entries = vec![0, 0, 0, 4, 0, 0, 4, 0];
+    entries.extend(filler(512));
+    let stz2 = full(b"stz2", 0, 0, &entries);
+    let err = parse_mp4_with(&stz2, &options).unwrap_err();
+    assert_eq!(err.kind, ParseErrorKind::AllocLimit(2048));
+    assert_eq!(err.path, "stz2");
+
+    // Brands fill the rest of the box
+    let mut brands = b"isom\0\0\0\0".to_vec();
+    brands.extend(filler(257 * 4));
+    let ftyp = boxed(b"ftyp", &brands);
+    let err = parse_mp4_with(&ftyp, &options).unwrap_err();
+    assert_eq!(err.kind, ParseErrorKind::AllocLimit(1028));
+
+    options.limits.max_alloc = 2048;
+    assert!(parse_mp4_with(&stz2, &options).is_ok());
+    assert!(parse_mp4_with(&ftyp, &options).is_ok());
+}
+
+#[test]
+fn reader_buffer_over_alloc_limit() {
+    let input = boxed(b"moov", &full(b"mvhd", 0, 0, &filler(96)));
+
+    let mut options = ParseOptions::default();
+    options.limits.max_alloc = 64;
+    let err = parse_mp4_reader_with(&mut Cursor::new(&input), &options).unwrap_err();
+    assert_eq!(err.kind, ParseErrorKind::AllocLimit(108));
+    assert_eq!(err.offset, 8);
+
+    options.limits.max_alloc = 108;
+    let output = parse_mp4_reader_with(&mut Cursor::new(&input), &options).unwrap();
+    assert_eq!(output.boxes.len(), 1);
+}
+
+#[test]
+fn stream_box_over_alloc_limit() {
+    let mut options = ParseOptions::default();
+    options.limits.max_alloc = 1 << 20;
+    let mut parser = Mp4StreamParser::with_options(options);
+
+    // mdat declaring a 2^40 byte largesize
+    let mut header = [0, 0, 0, 1].to_vec();
+    header.extend(b"mdat");
+    header.extend((1u64 << 40).to_be_bytes());
+    let err = parser.feed(&header).unwrap_err();
+    assert_eq!(err.kind, ParseErrorKind::AllocLimit(1 << 40));
+    assert_eq!(err.offset, 0);
+
+    // Nothing more is buffered for it
+    let err = parser.feed(&filler(64)).unwrap_err();
+    assert_eq!(err.kind, ParseErrorKind::AllocLimit(1 << 40));
+    assert_eq!(parser.needed(), Some(0));
+}
+
+#[test]
+fn boxes_too_deep() {
+    let mut input = full(b"mfhd", 0, 0, &[0, 0, 0, 1]);
+    for _ in 0..3 {
+        input = boxed(b"moof", &input);
+    }
+
+    let mut options = ParseOptions::default();
+    options.limits.max_depth = 3;
+    let err = parse_mp4_with(&input, &options).unwrap_err();
+    assert_eq!(err.kind, ParseErrorKind::TooDeep(4));
+    assert_eq!(err.path, "moof/moof/moof");
+
+    let err = visit_mp4_with(&input, &mut Walk, &options).unwrap_err();
+    assert_eq!(err.kind, ParseErrorKind::TooDeep(4));
+
+    options.limits.max_depth = 4;
+    assert!(parse_mp4_with(&input, &options).is_ok());
+}
+
+// Visits everything with the default callbacks
+struct Walk;
+
+impl BoxVisitor for Walk {}
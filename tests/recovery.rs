@@ -0,0 +1,116 @@
+mod common;
+
+use bmf_parse::*;
+use common::*;
+use std::io::Cursor;
+
+fn recover(strictness: Strictness) -> ParseOptions {
+    ParseOptions {
+        recover: true,
+        strictness,
+        ..ParseOptions::default()
+    }
+}
+
+#[test]
+fn failed_box_is_skipped_whole() {
+    // Trailing bytes are an error in strict mode, the moov header itself is sound
+    let moov = boxed(
+        b"moov",
+        &[full(b"mvhd", 0, 0, &filler(96)), vec![0; 4]].concat(),
+    );
+    let input = [moov, boxed(b"free", &[])].concat();
+
+    let output = parse_mp4_with(&input, &recover(Strictness::Strict)).unwrap();
+    assert_eq!(output.boxes.len(), 1);
+    assert!(matches!(output.boxes[0], Mp4Box::Free(_)));
+
+    assert_eq!(output.skipped.len(), 1);
+    assert_eq!(output.skipped[0].range, 0..120);
+    let error = &output.skipped[0].error;
+    assert_eq!(
+        error.kind,
+        ParseErrorKind::Nonconformant(ParseWarningKind::TrailingBytes(4))
+    );
+    assert_eq!(error.path, "moov");
+}
+
+#[test]
+fn garbage_is_skipped_to_next_box() {
+    let mut input = vec![0xff; 5];
+    input.extend(full(b"mfhd", 0, 0, &[0, 0, 0, 1]));
+    // Header claiming more than the input holds
+    input.extend([0, 0, 1, 0]);
+    input.extend(b"moof");
+    input.extend(boxed(b"free", &[]));
+
+    let output = parse_mp4_with(&input, &recover(Strictness::Lenient)).unwrap();
+    assert_eq!(output.boxes.len(), 2);
+    assert!(matches!(output.boxes[0], Mp4Box::Mfhd(_)));
+    assert!(matches!(output.boxes[1], Mp4Box::Free(_)));
+
+    let ranges: Vec<_> = output
+        .skipped
+        .iter()
+        .map(|skipped| skipped.range.clone())
+        .collect();
+    assert_eq!(ranges, [0..5, 21..29]);
+    assert_eq!(
+        output.skipped[1].error.kind,
+        ParseErrorKind::Truncated {
+            needed: 248,
+            available: 8
+        }
+    );
+
+    // Without recovery the first bad header fails the parse
+    assert!(parse_mp4(&input).is_err());
+}
+
+#[test]
+fn reader_skips_failed_box() {
+    let moov = boxed(
+        b"moov",
+        &[full(b"mvhd", 0, 0, &filler(96)), vec![0; 4]].concat(),
+    );
+    let input = [moov, boxed(b"free", &[])].concat();
+
+    let output =
+        parse_mp4_reader_with(&mut Cursor::new(&input), &recover(Strictness::Strict)).unwrap();
+    assert_eq!(output.boxes.len(), 1);
+    assert!(matches!(output.boxes[0], Mp4Box::Free(_)));
+    assert_eq!(output.skipped.len(), 1);
+    assert_eq!(output.skipped[0].range, 0..120);
+    assert_eq!(output.skipped[0].error.path, "moov");
+
+    // After a bad header the reader scans ahead for the next one
+    let input = [vec![0xff; 8], boxed(b"free", &[])].concat();
+    let output =
+        parse_mp4_reader_with(&mut Cursor::new(&input), &recover(Strictness::Lenient)).unwrap();
+    assert_eq!(output.boxes.len(), 1);
+    assert!(matches!(output.boxes[0], Mp4Box::Free(_)));
+    assert_eq!(output.skipped.len(), 1);
+    assert_eq!(output.skipped[0].range, 0..8);
+    assert!(matches!(
+        output.skipped[0].error.kind,
+        ParseErrorKind::Truncated { .. }
+    ));
+}
+
+#[test]
+fn reader_resyncs_across_windows() {
+    // Further than one 64 KiB read window
+    let garbage = vec![0xff; 100_000];
+    let input = [garbage, boxed(b"free", &[]), vec![0xff; 8]].concat();
+
+    let output =
+        parse_mp4_reader_with(&mut Cursor::new(&input), &recover(Strictness::Lenient)).unwrap();
+    assert_eq!(output.boxes.len(), 1);
+    assert!(matches!(output.boxes[0], Mp4Box::Free(_)));
+    let ranges =
+        |skipped: &[Skipped]| -> Vec<_> { skipped.iter().map(|skip| skip.range.clone()).collect() };
+    assert_eq!(ranges(&output.skipped), [0..100_000, 100_008..100_016]);
+
+    let output = parse_mp4_with(&input, &recover(Strictness::Lenient)).unwrap();
+    assert_eq!(ranges(&output.skipped), [0..100_000, 100_008..100_016]);
+}
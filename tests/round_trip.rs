@@ -0,0 +1,38 @@
+mod common;
+
+use bmf_parse::*;
+use std::io::Cursor;
+
+// Writing an untouched tree reproduces its input, whichever way it was parsed
+#[test]
+fn parse_mp4_round_trip() {
+    let input = common::sample_file();
+    let boxes = parse_mp4(&input).unwrap();
+    assert!(write_mp4(&boxes).unwrap() == input);
+}
+
+#[test]
+fn parse_mp4_ref_round_trip() {
+    let input = common::sample_file();
+    let boxes = parse_mp4_ref(&input).unwrap();
+    let boxes: Vec<_> = boxes.into_iter().map(Mp4BoxRef::into_owned).collect();
+    assert!(write_mp4(&boxes).unwrap() == input);
+}
+
+#[test]
+fn parse_mp4_reader_round_trip() {
+    let input = common::sample_file();
+    let mut reader = Cursor::new(&input);
+    let mut boxes = parse_mp4_reader(&mut reader).unwrap();
+    load_payloads(&mut boxes, &mut reader).unwrap();
+    assert!(write_mp4(&boxes).unwrap() == input);
+}
+
+#[test]
+fn unloaded_payload_fails_to_write() {
+    let input = common::sample_file();
+    let boxes = parse_mp4_reader(&mut Cursor::new(&input)).unwrap();
+
+    let err = write_mp4(&boxes).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+}
@@ -0,0 +1,81 @@
+mod common;
+
+use bmf_parse::*;
+use common::*;
+
+#[test]
+fn failing_box_keeps_completed_boxes() {
+    let mut input = boxed(b"free", &[0; 3]);
+    input.extend(full(b"mfhd", 0, 0, &[0, 0]));
+
+    let mut parser = Mp4StreamParser::new();
+    let boxes = parser.feed(&input).unwrap();
+    assert_eq!(boxes.len(), 1);
+    assert!(matches!(boxes[0], Mp4Box::Free(_)));
+    assert_eq!(parser.offset(), 11);
+
+    // The truncated mfhd stays buffered and fails on the next call
+    let err = parser.feed(&[]).unwrap_err();
+    assert_eq!(err.path, "mfhd");
+    assert_eq!(parser.offset(), 11);
+}
+
+#[test]
+fn chunked_feed_matches_parse_mp4() {
+    let input = sample_file();
+
+    for chunk_size in [1, 7, 64, input.len()] {
+        let mut parser = Mp4StreamParser::new();
+        let mut boxes = vec![];
+        for chunk in input.chunks(chunk_size) {
+            boxes.extend(parser.feed(chunk).unwrap());
+        }
+
+        // The last mdat runs to the end of the stream
+        assert_eq!(parser.needed(), None);
+        boxes.extend(parser.finish().unwrap());
+        assert_eq!(parser.offset(), input.len() as u64);
+
+        assert_eq!(boxes.len(), parse_mp4(&input).unwrap().len());
+        assert!(write_mp4(&boxes).unwrap() == input);
+    }
+}
+
+#[test]
+fn needed_counts_missing_bytes() {
+    let input = full(b"mfhd", 0, 0, &[0, 0, 0, 1]);
+    let mut parser = Mp4StreamParser::new();
+
+    assert_eq!(parser.needed(), Some(8));
+    assert!(parser.feed(&input[..4]).unwrap().is_empty());
+    assert_eq!(parser.needed(), Some(4));
+    assert!(parser.feed(&input[4..10]).unwrap().is_empty());
+    assert_eq!(parser.needed(), Some(6));
+
+    let boxes = parser.feed(&input[10..]).unwrap();
+    assert!(matches!(boxes[..], [Mp4Box::Mfhd(_)]));
+    assert_eq!(parser.needed(), Some(8));
+}
+
+#[test]
+fn stream_options_apply() {
+    // hdlr name without a NUL terminator
+    let input = full(b"hdlr", 0, 0, &[&filler(20)[..], b"name"].concat());
+
+    let mut parser = Mp4StreamParser::new();
+    assert_eq!(parser.feed(&input).unwrap().len(), 1);
+    assert_eq!(
+        parser.warnings()[0].kind,
+        ParseWarningKind::UnterminatedString
+    );
+
+    let mut parser = Mp4StreamParser::with_options(ParseOptions {
+        strictness: Strictness::Strict,
+        ..ParseOptions::default()
+    });
+    let err = parser.feed(&input).unwrap_err();
+    assert_eq!(
+        err.kind,
+        ParseErrorKind::Nonconformant(ParseWarningKind::UnterminatedString)
+    );
+}
@@ -0,0 +1,93 @@
+mod common;
+
+use bmf_parse::*;
+use common::*;
+
+fn strict() -> ParseOptions {
+    ParseOptions {
+        strictness: Strictness::Strict,
+        ..ParseOptions::default()
+    }
+}
+
+#[test]
+fn lenient_collects_warnings() {
+    let input = sample_file();
+    let output = parse_mp4_with(&input, &ParseOptions::default()).unwrap();
+
+    let warnings: Vec<_> = output
+        .warnings
+        .iter()
+        .map(|warning| (warning.offset, warning.path.as_str(), &warning.kind))
+        .collect();
+    assert_eq!(
+        warnings,
+        [
+            (24, "ftyp", &ParseWarningKind::TrailingBytes(2)),
+            (
+                471,
+                "moov/trak/mdia/hdlr",
+                &ParseWarningKind::UnterminatedString
+            ),
+            (2956, "moov", &ParseWarningKind::TrailingBytes(4)),
+        ]
+    );
+    // Warnings don't change what is written back
+    assert!(write_mp4(&output.boxes).unwrap() == input);
+}
+
+#[test]
+fn strict_fails_on_first_violation() {
+    let err = parse_mp4_with(&sample_file(), &strict()).unwrap_err();
+    assert_eq!(
+        err.kind,
+        ParseErrorKind::Nonconformant(ParseWarningKind::TrailingBytes(2))
+    );
+    assert_eq!(err.offset, 24);
+    assert_eq!(err.path, "ftyp");
+    assert_eq!(err.field, Some("trailing"));
+}
+
+#[test]
+fn sample_count_mismatch() {
+    let stbl = [
+        full(b"stts", 0, 0, &[0, 0, 0, 1, 0, 0, 0, 3, 0, 0, 4, 0]),
+        full(b"stsz", 0, 0, &[0, 0, 1, 0, 0, 0, 0, 2]),
+    ]
+    .concat();
+    let input = boxed(b"stbl", &stbl);
+
+    let output = parse_mp4_with(&input, &ParseOptions::default()).unwrap();
+    assert_eq!(output.warnings.len(), 1);
+    assert_eq!(
+        output.warnings[0].kind,
+        ParseWarningKind::SampleCountMismatch { stsz: 2, stts: 3 }
+    );
+
+    let err = parse_mp4_with(&input, &strict()).unwrap_err();
+    assert_eq!(
+        err.kind,
+        ParseErrorKind::Nonconformant(ParseWarningKind::SampleCountMismatch { stsz: 2, stts: 3 })
+    );
+}
+
+#[test]
+fn strict_accepts_per_sample_saiz() {
+    // One info size per sample when there is no default
+    let input = full(b"saiz", 0, 0, &[0, 0, 0, 0, 3, 8, 16, 24]);
+    let boxes = parse_mp4_with(&input, &strict()).unwrap().boxes;
+    assert!(write_mp4(&boxes).unwrap() == input);
+
+    let saiz = match &boxes[0] {
+        Mp4Box::Saiz(saiz) => saiz,
+        box_ => panic!("expected saiz, found {:?}", box_),
+    };
+    let sizes: Vec<u8> = saiz
+        .sample_info_size
+        .as_ref()
+        .unwrap()
+        .iter()
+        .map(|entry| entry.size)
+        .collect();
+    assert_eq!(sizes, [8, 16, 24]);
+}
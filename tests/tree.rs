@@ -0,0 +1,22 @@
+mod common;
+
+use bmf_parse::{boxes::*, *};
+
+#[test]
+fn find_box_reaches_sample_entries() {
+    let mut boxes = parse_mp4(&common::sample_file()).unwrap();
+
+    assert!(matches!(find_box(&boxes, b"avcC"), Some(Mp4Box::Avcc(_))));
+    assert!(matches!(find_box(&boxes, b"dfLa"), Some(Mp4Box::Dfla(_))));
+    // encv/sinf/schi/tenc or its enca twin
+    let kid: Vec<u8> = (0x10..0x20).collect();
+    match find_box(&boxes, b"tenc") {
+        Some(Mp4Box::Tenc(tenc)) => assert_eq!(tenc.default_kid[..], kid),
+        box_ => panic!("expected tenc, found {:?}", box_),
+    }
+
+    match find_box_mut(&mut boxes, b"dac3") {
+        Some(Mp4Box::Dac3(_)) => {}
+        box_ => panic!("expected dac3, found {:?}", box_),
+    }
+}
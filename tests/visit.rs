@@ -0,0 +1,180 @@
+mod common;
+
+use bmf_parse::*;
+use common::*;
+
+// Records callbacks as "enter moof", "box mfhd" and "leave mfhd". Answers with the
+// action listed for the box type, `Continue` for the rest
+#[derive(Default)]
+struct Recorder {
+    events: Vec<String>,
+    enter: Vec<([u8; 4], Visit)>,
+    on_box: Vec<([u8; 4], Visit)>,
+}
+
+impl Recorder {
+    fn record(&mut self, event: &str, path: &[[u8; 4]]) -> [u8; 4] {
+        let box_type = *path.last().unwrap();
+        self.events
+            .push(format!("{} {}", event, String::from_utf8_lossy(&box_type)));
+        box_type
+    }
+}
+
+fn action(actions: &[([u8; 4], Visit)], box_type: [u8; 4]) -> Visit {
+    actions
+        .iter()
+        .find(|(type_, _)| *type_ == box_type)
+        .map_or(Visit::Continue, |(_, visit)| *visit)
+}
+
+impl BoxVisitor for Recorder {
+    fn enter_box(&mut self, path: &[[u8; 4]], header: &BoxHeader, _offset: u64) -> Visit {
+        assert_eq!(path.last(), Some(&header.box_type));
+        let box_type = self.record("enter", path);
+        action(&self.enter, box_type)
+    }
+
+    fn leave_box(&mut self, path: &[[u8; 4]]) -> Visit {
+        self.record("leave", path);
+        Visit::Continue
+    }
+
+    fn on_box(&mut self, path: &[[u8; 4]], _box_: &Mp4Box) -> Visit {
+        let box_type = self.record("box", path);
+        action(&self.on_box, box_type)
+    }
+}
+
+fn input() -> Vec<u8> {
+    let traf = [
+        full(b"tfdt", 0, 0, &filler(8)),
+        full(b"trun", 0, 0, &[0; 4]),
+    ]
+    .concat();
+    let moof = [full(b"mfhd", 0, 0, &[0, 0, 0, 1]), boxed(b"traf", &traf)].concat();
+    [boxed(b"moof", &moof), boxed(b"free", &[])].concat()
+}
+
+fn visit(recorder: &mut Recorder) -> Vec<&str> {
+    visit_mp4(&input(), recorder).unwrap();
+    recorder.events.iter().map(String::as_str).collect()
+}
+
+#[test]
+fn visits_in_file_order() {
+    let mut recorder = Recorder::default();
+    assert_eq!(
+        visit(&mut recorder),
+        [
+            "enter moof",
+            "enter mfhd",
+            "box mfhd",
+            "leave mfhd",
+            "enter traf",
+            "enter tfdt",
+            "box tfdt",
+            "leave tfdt",
+            "enter trun",
+            "box trun",
+            "leave trun",
+            "leave traf",
+            "leave moof",
+            "enter free",
+            "box free",
+            "leave free",
+        ]
+    );
+}
+
+#[test]
+fn skip_from_enter_box_passes_over_box() {
+    let mut recorder = Recorder {
+        enter: vec![(*b"traf", Visit::Skip)],
+        ..Recorder::default()
+    };
+    assert_eq!(
+        visit(&mut recorder),
+        [
+            "enter moof",
+            "enter mfhd",
+            "box mfhd",
+            "leave mfhd",
+            "enter traf",
+            "leave moof",
+            "enter free",
+            "box free",
+            "leave free",
+        ]
+    );
+}
+
+#[test]
+fn skip_from_on_box_passes_over_parent() {
+    let mut recorder = Recorder {
+        on_box: vec![(*b"mfhd", Visit::Skip)],
+        ..Recorder::default()
+    };
+    assert_eq!(
+        visit(&mut recorder),
+        [
+            "enter moof",
+            "enter mfhd",
+            "box mfhd",
+            "leave mfhd",
+            "leave moof",
+            "enter free",
+            "box free",
+            "leave free",
+        ]
+    );
+}
+
+#[test]
+fn stop_ends_walk() {
+    let mut recorder = Recorder {
+        on_box: vec![(*b"tfdt", Visit::Stop)],
+        ..Recorder::default()
+    };
+    assert_eq!(
+        visit(&mut recorder),
+        [
+            "enter moof",
+            "enter mfhd",
+            "box mfhd",
+            "leave mfhd",
+            "enter traf",
+            "enter tfdt",
+            "box tfdt"
+        ]
+    );
+}
+
+#[test]
+fn warns_like_parse_mp4() {
+    let options = ParseOptions::default();
+    let stbl = [
+        full(b"stts", 0, 0, &[0, 0, 0, 1, 0, 0, 0, 3, 0, 0, 4, 0]),
+        full(b"stsz", 0, 0, &[0, 0, 1, 0, 0, 0, 0, 2]),
+    ]
+    .concat();
+    let mismatch = boxed(b"stbl", &stbl);
+
+    // The sample file's moov ends in padding
+    for input in [sample_file(), mismatch] {
+        let warnings = visit_mp4_with(&input, &mut Recorder::default(), &options).unwrap();
+        assert_eq!(warnings, parse_mp4_with(&input, &options).unwrap().warnings);
+    }
+
+    let strict = ParseOptions {
+        strictness: Strictness::Strict,
+        ..ParseOptions::default()
+    };
+    let input = boxed(b"moov", &[0; 4]);
+    let err = visit_mp4_with(&input, &mut Recorder::default(), &strict).unwrap_err();
+    assert_eq!(
+        err.kind,
+        ParseErrorKind::Nonconformant(ParseWarningKind::TrailingBytes(4))
+    );
+    assert_eq!(err, parse_mp4_with(&input, &strict).unwrap_err());
+}